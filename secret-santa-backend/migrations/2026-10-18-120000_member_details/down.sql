@@ -0,0 +1,3 @@
+DROP TABLE exclusions;
+
+ALTER TABLE members DROP COLUMN wishlist;
@@ -0,0 +1,13 @@
+ALTER TABLE members ADD COLUMN wishlist TEXT;
+
+CREATE TABLE exclusions (
+    id INT GENERATED ALWAYS AS IDENTITY,
+    group_id INT NOT NULL,
+    user_id INT NOT NULL,
+    excluded_user_id INT NOT NULL,
+    CONSTRAINT unique_group_user_excluded_id UNIQUE(group_id, user_id, excluded_user_id),
+    CONSTRAINT exclusions_pkey PRIMARY KEY(id),
+    CONSTRAINT fk_user FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
+    CONSTRAINT fk_excluded_user FOREIGN KEY(excluded_user_id) REFERENCES users(id) ON DELETE CASCADE,
+    CONSTRAINT fk_group FOREIGN KEY(group_id) REFERENCES sgroups(id) ON DELETE CASCADE
+);
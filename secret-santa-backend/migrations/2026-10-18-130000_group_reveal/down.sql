@@ -0,0 +1 @@
+ALTER TABLE sgroups DROP COLUMN is_revealed;
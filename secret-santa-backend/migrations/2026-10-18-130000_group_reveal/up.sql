@@ -0,0 +1 @@
+ALTER TABLE sgroups ADD COLUMN is_revealed BOOLEAN DEFAULT FALSE NOT NULL;
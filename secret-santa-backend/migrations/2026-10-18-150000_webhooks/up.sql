@@ -0,0 +1,26 @@
+-- Webhooks without a group receive the events of every group. Webhooks
+-- imported from a dump without secrets have none and get no deliveries until
+-- they are registered again.
+CREATE TABLE webhooks (
+    id INT GENERATED ALWAYS AS IDENTITY,
+    group_id INT,
+    url TEXT NOT NULL,
+    secret TEXT,
+    CONSTRAINT webhooks_pkey PRIMARY KEY(id),
+    CONSTRAINT fk_group FOREIGN KEY(group_id) REFERENCES sgroups(id) ON DELETE CASCADE
+);
+
+CREATE TABLE webhook_deliveries (
+    id INT GENERATED ALWAYS AS IDENTITY,
+    webhook_id INT NOT NULL,
+    event VARCHAR(50) NOT NULL,
+    attempt INT NOT NULL,
+    status_code INT,
+    error TEXT,
+    success BOOLEAN NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    CONSTRAINT webhook_deliveries_pkey PRIMARY KEY(id),
+    CONSTRAINT fk_webhook FOREIGN KEY(webhook_id) REFERENCES webhooks(id) ON DELETE CASCADE
+);
+
+CREATE INDEX webhook_deliveries_webhook_id ON webhook_deliveries(webhook_id, id);
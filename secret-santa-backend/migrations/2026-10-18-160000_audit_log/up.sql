@@ -0,0 +1,17 @@
+-- Entries outlive their group, so group_id is not a foreign key; it is NULL
+-- when the action named a group that doesn't exist. Operator actions that
+-- concern no single group, like registering a webhook for every group or
+-- deleting a user who is in none, have no group_name either.
+CREATE TABLE audit_log (
+    id INT GENERATED ALWAYS AS IDENTITY,
+    group_id INT,
+    group_name TEXT,
+    actor TEXT,
+    action VARCHAR(30) NOT NULL,
+    target TEXT,
+    outcome VARCHAR(50) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    CONSTRAINT audit_log_pkey PRIMARY KEY(id)
+);
+
+CREATE INDEX audit_log_group_id ON audit_log(group_id, id);
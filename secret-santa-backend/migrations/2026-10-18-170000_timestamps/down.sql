@@ -0,0 +1,8 @@
+DROP TRIGGER set_updated_at ON santas;
+ALTER TABLE santas DROP COLUMN created_at, DROP COLUMN updated_at;
+DROP TRIGGER set_updated_at ON members;
+ALTER TABLE members DROP COLUMN created_at, DROP COLUMN updated_at;
+DROP TRIGGER set_updated_at ON sgroups;
+ALTER TABLE sgroups DROP COLUMN created_at, DROP COLUMN updated_at;
+DROP TRIGGER set_updated_at ON users;
+ALTER TABLE users DROP COLUMN created_at, DROP COLUMN updated_at;
@@ -0,0 +1,20 @@
+-- Rows that predate this migration get the time it ran.
+ALTER TABLE users
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+SELECT diesel_manage_updated_at('users');
+
+ALTER TABLE sgroups
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+SELECT diesel_manage_updated_at('sgroups');
+
+ALTER TABLE members
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+SELECT diesel_manage_updated_at('members');
+
+ALTER TABLE santas
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+SELECT diesel_manage_updated_at('santas');
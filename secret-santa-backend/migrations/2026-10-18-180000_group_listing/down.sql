@@ -0,0 +1,2 @@
+DROP INDEX sgroups_updated_at;
+DROP INDEX sgroups_created_at;
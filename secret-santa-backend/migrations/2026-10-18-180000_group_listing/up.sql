@@ -0,0 +1,3 @@
+-- Keyset pagination of /get-groups by timestamp; sorting by name uses unique_gname.
+CREATE INDEX sgroups_created_at ON sgroups(created_at, id);
+CREATE INDEX sgroups_updated_at ON sgroups(updated_at, id);
@@ -0,0 +1 @@
+ALTER TABLE sgroups DROP COLUMN budget, DROP COLUMN draw_date, DROP COLUMN capacity;
@@ -0,0 +1,6 @@
+-- Optional settings chosen when a group is created: the most members it takes,
+-- the day of the draw, and the gift budget in whole currency units.
+ALTER TABLE sgroups
+    ADD COLUMN capacity INT CHECK (capacity >= 3),
+    ADD COLUMN draw_date DATE,
+    ADD COLUMN budget INT CHECK (budget >= 0);
@@ -0,0 +1 @@
+ALTER TABLE sgroups DROP COLUMN member_visibility;
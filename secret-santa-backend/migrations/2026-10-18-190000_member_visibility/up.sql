@@ -0,0 +1,2 @@
+-- Who may list the members: 0 admins, 1 members, 2 every registered user.
+ALTER TABLE sgroups ADD COLUMN member_visibility INT NOT NULL DEFAULT 0;
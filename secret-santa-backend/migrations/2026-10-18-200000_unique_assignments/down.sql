@@ -0,0 +1,3 @@
+ALTER TABLE santas DROP CONSTRAINT unique_group_recipient_id;
+ALTER TABLE santas DROP CONSTRAINT unique_group_santa_id;
+ALTER TABLE santas ADD CONSTRAINT unique_group_santa_recipient_id UNIQUE(group_id, santa_id, recipient_id);
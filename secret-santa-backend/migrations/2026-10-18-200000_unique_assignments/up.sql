@@ -0,0 +1,5 @@
+-- Every member gives exactly one present and gets exactly one per group, so a
+-- group can't be drawn twice even if two draws race.
+ALTER TABLE santas DROP CONSTRAINT unique_group_santa_recipient_id;
+ALTER TABLE santas ADD CONSTRAINT unique_group_santa_id UNIQUE(group_id, santa_id);
+ALTER TABLE santas ADD CONSTRAINT unique_group_recipient_id UNIQUE(group_id, recipient_id);
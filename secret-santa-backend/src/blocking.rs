@@ -0,0 +1,109 @@
+//! Threads for the blocking work of requests, above all Diesel queries, so
+//! that a slow query only holds up its own request.
+
+use crate::logging;
+use async_std::channel::{self, Receiver, Sender};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use tide::log;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Runs jobs until the pool is dropped; a panicking job is logged and doesn't
+/// take the thread down.
+fn run(receiver: Receiver<Job>) {
+    while let Ok(job) = receiver.recv_blocking() {
+        if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+            log::error!("Blocking job panicked");
+        }
+    }
+}
+
+struct Worker {
+    sender: Option<Sender<Job>>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl Drop for Worker {
+    /// Finishes the queued jobs before the process exits.
+    fn drop(&mut self) {
+        self.sender.take();
+        let current = thread::current().id();
+        for handle in self.handles.drain(..) {
+            // The last clone of the pool may be dropped by one of its own jobs.
+            if handle.thread().id() != current {
+                let _ = handle.join();
+            }
+        }
+    }
+}
+
+/// A fixed number of threads for blocking work. When every thread is busy,
+/// further jobs wait in a queue as long as the pool, and when that is full
+/// too their tasks wait without blocking the executor.
+#[derive(Clone)]
+pub struct BlockingPool {
+    worker: Arc<Worker>,
+}
+
+impl BlockingPool {
+    pub fn new(threads: usize) -> Self {
+        let (sender, receiver) = channel::bounded::<Job>(threads);
+        let handles = (0..threads)
+            .map(|i| {
+                let receiver = receiver.clone();
+                thread::Builder::new()
+                    .name(format!("blocking-{i}"))
+                    .spawn(move || run(receiver))
+                    .expect("cannot spawn blocking thread")
+            })
+            .collect();
+        Self {
+            worker: Arc::new(Worker { sender: Some(sender), handles }),
+        }
+    }
+
+    /// Runs `f` on one of the pool's threads, as part of the current request,
+    /// and waits for its result. Returns `None` if `f` panicked.
+    pub async fn run<T: Send + 'static>(&self, f: impl FnOnce() -> T + Send + 'static) -> Option<T> {
+        let (result_sender, result_receiver) = channel::bounded(1);
+        let request_id = logging::request_id();
+        let job: Job = Box::new(move || {
+            let result = logging::with_request_id(request_id, f);
+            let _ = result_sender.try_send(result);
+        });
+        self.worker.sender.as_ref()?.send(job).await.ok()?;
+        result_receiver.recv().await.ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn slow_job_does_not_stall_others() {
+        let pool = BlockingPool::new(2);
+        let (release, released) = mpsc::channel::<()>();
+        let slow = async_std::task::spawn({
+            let pool = pool.clone();
+            async move { pool.run(move || released.recv_timeout(Duration::from_secs(5)).is_ok()).await }
+        });
+
+        let fast = async_std::task::block_on(pool.run(|| 42));
+        assert_eq!(fast, Some(42));
+        release.send(()).unwrap();
+        assert_eq!(async_std::task::block_on(slow), Some(true));
+    }
+
+    #[test]
+    fn survives_panicking_jobs() {
+        let pool = BlockingPool::new(1);
+        let panicked = async_std::task::block_on(pool.run(|| -> i32 { panic!("job failed") }));
+        assert_eq!(panicked, None);
+        assert_eq!(async_std::task::block_on(pool.run(|| 1)), Some(1));
+    }
+}
@@ -0,0 +1,88 @@
+use crate::config::Args;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Secret Santa service backend", long_about = None)]
+pub struct Cli {
+    #[command(flatten)]
+    pub args: Args,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run the HTTP service (the default)
+    Serve,
+    /// Apply pending database migrations and exit
+    Migrate,
+    /// Register a new user
+    CreateUser {
+        username: String,
+        /// Address for notification emails
+        #[arg(long)]
+        email: Option<String>,
+    },
+    /// List groups
+    ListGroups {
+        /// Include closed groups
+        #[arg(long)]
+        all: bool,
+    },
+    /// Show a group's state and members
+    InspectGroup {
+        group_name: String,
+    },
+    /// Run the draw for a group without an admin of the group
+    ForceClose {
+        group_name: String,
+    },
+    /// Delete a user, unless they are in a closed group or the last admin of a group
+    DeleteUser {
+        username: String,
+    },
+    /// Register users from a CSV and add them to an open group
+    ImportMembers {
+        group_name: String,
+        /// CSV with a username column and optional exclude and wishlist columns
+        file: PathBuf,
+    },
+    /// Register a webhook for one group or, without --group, for every group
+    AddWebhook {
+        url: String,
+        /// Key of the HMAC-SHA256 signature sent with every event
+        #[arg(long, env = "SANTA_WEBHOOK_SECRET", hide_env_values = true)]
+        secret: String,
+        #[arg(long)]
+        group: Option<String>,
+    },
+    /// List all webhooks
+    ListWebhooks,
+    /// Delete a webhook and its delivery log
+    DeleteWebhook {
+        id: i32,
+    },
+    /// Write all data as versioned JSON
+    Export {
+        /// Output file instead of standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Include the secrets of webhooks, which are left out otherwise
+        #[arg(long)]
+        include_secrets: bool,
+    },
+    /// Load data written by `export`
+    Import {
+        file: PathBuf,
+        /// Replace existing data instead of refusing to import into a non-empty database
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// Parses the command line after loading `.env`, so that it can supply flag values.
+pub fn parse() -> Cli {
+    dotenv::dotenv().ok();
+    Cli::parse()
+}
@@ -1,104 +1,446 @@
-use crate::errors;
-use crate::models::{User, NewUser, Group, NewGroup, Member, NewMember, Role, NewSanta};
+use crate::dump::{
+    Dump, DumpError, DumpExclusion, DumpGroup, DumpMember, DumpSanta, DumpTimestamps, DumpUser,
+    DumpWebhook, ImportSummary, DUMP_VERSION,
+};
+use self::audit::{Audit, Recorded};
+use crate::blocking::BlockingPool;
+use crate::errors::ServiceError;
+use crate::events::{EventEnvelope, EventStreams, GroupEvent, StreamEvent};
+use crate::member_import::{self, ImportRow, Rejection};
+use crate::metrics::metrics;
+use crate::notifications::{self, Notification, Notifier};
+use crate::pagination::{self, GroupCursor};
+use crate::webhooks::{self, Delivery, DeliveryAttempt, UrlPolicy, Webhooks};
+use crate::json_models::{
+    AssignmentResponse, AssignmentsResponse, AuditEntryResponse, AuditLogResponse, CheckStatus,
+    GroupDetailsResponse, GroupMembersResponse, GroupResponse, GroupSettings, GroupSort, GroupsQuery,
+    GroupsResponse, ImportMembersResponse, ImportRowReport, ImportRowStatus, MemberResponse, MyGroupResponse,
+    MyGroupsResponse, ReadinessResponse,
+    RecipientResponse, SortOrder, StatusResponse, WebhookDeliveriesResponse, WebhookDeliveryResponse,
+    WebhookResponse, WebhooksResponse,
+};
+use crate::models::{
+    User, NewUser, Group, NewGroup, Member, NewMember, Role, NewSanta, Santa, Exclusion,
+    NewExclusion, Webhook, NewWebhook, WebhookDelivery, NewWebhookDelivery, AuditAction,
+    AuditEntry, NewAuditEntry, MemberVisibility,
+};
+use crate::config::Config;
+use async_std::channel::Receiver;
+use chrono::Utc;
 use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool, PoolError, PooledConnection};
 use diesel::result::{DatabaseErrorKind, Error::DatabaseError};
-use diesel::{Connection, PgConnection};
-use dotenv::dotenv;
+use diesel::PgConnection;
+use diesel::migration::{MigrationSource, MigrationVersion};
+use diesel::pg::Pg;
+use diesel::sql_types::{BigInt, Nullable};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 use rand::{seq::SliceRandom, thread_rng};
-use serde_json::json;
-use std::env;
-use tide::{log, StatusCode};
+use std::collections::{HashMap, HashSet};
+use std::ops::DerefMut;
+use std::time::Duration;
+use tide::log;
+
+pub type PgPool = Pool<ConnectionManager<PgConnection>>;
+
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+/// How long a readiness probe waits for a pooled connection.
+const READINESS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Random orders tried by the draw before deciding that a group's exclusions
+/// can't be satisfied.
+const DRAW_ATTEMPTS: usize = 1000;
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+/// How a transaction locks the row of a group it depends on. Adding members
+/// takes a share lock, so members can join at the same time; anything that
+/// changes the group itself or relies on its set of members or admins staying
+/// put, like the draw or revoking an admin, takes an update lock and waits for
+/// them.
+#[derive(Debug, Clone, Copy)]
+enum GroupLock {
+    Share,
+    Update,
+}
+
+impl GroupLock {
+    /// The lock for adding members to a group. Groups with a capacity are filled
+    /// one transaction at a time, so that joins can't overfill them together.
+    /// The capacity is fixed when the group is created.
+    fn to_add_members(group: &Group) -> Self {
+        match group.capacity {
+            Some(_) => Self::Update,
+            None => Self::Share,
+        }
+    }
+}
+
+mod audit {
+    use super::{DB, PgConn};
+    use crate::errors::ServiceError;
+    use crate::models::{AuditAction, Group, NewAuditEntry};
+    use std::cell::RefCell;
+
+    /// The audit entry of one operation; see [`Database::audited`](super::Database::audited).
+    pub(super) struct Audit<'a> {
+        pub(super) action: AuditAction,
+        pub(super) actor: Option<&'a str>,
+        target: Option<&'a str>,
+        group: RefCell<Option<(i32, String)>>,
+    }
+
+    /// Proof that an operation recorded its success, which it hands back to
+    /// [`Database::audited`](super::Database::audited) with its result. Only
+    /// [`Audit::record`] makes one.
+    #[must_use]
+    pub(super) struct Recorded(());
+
+    impl<'a> Audit<'a> {
+        pub(super) fn new(action: AuditAction, actor: Option<&'a str>, target: Option<&'a str>) -> Self {
+            Self { action, actor, target, group: RefCell::new(None) }
+        }
+
+        /// Notes the group the operation acts on, in whose log a failure of the
+        /// operation is recorded.
+        pub(super) fn resolved(&self, group: &Group) {
+            self.group.replace(Some((group.id, group.gname.clone())));
+        }
+
+        /// The id and name of the group the operation last resolved.
+        pub(super) fn group(&self) -> Option<(i32, String)> {
+            self.group.borrow().clone()
+        }
+
+        pub(super) fn entry<'e>(&'e self, group_id: Option<i32>, group_name: Option<&'e str>, outcome: &'e str) -> NewAuditEntry<'e> {
+            NewAuditEntry {
+                group_id,
+                group_name,
+                actor: self.actor,
+                action: self.action.name(),
+                target: self.target,
+                outcome,
+            }
+        }
+
+        /// Records that the operation succeeded on `group`, or on no single group.
+        /// Called in the transaction of the change, so that the entry is committed
+        /// with it or not at all.
+        pub(super) fn record(&self, db: &mut DB<impl PgConn>, group: Option<&Group>) -> Result<Recorded, ServiceError> {
+            let entry = self.entry(group.map(|group| group.id), group.map(|group| group.gname.as_str()), "ok");
+            db.log_audit_entry(&entry)?;
+            Ok(Recorded(()))
+        }
+
+        /// Records that the operation succeeded on each of `groups`, or on no
+        /// single group if there are none.
+        pub(super) fn record_each(&self, db: &mut DB<impl PgConn>, groups: &[Group]) -> Result<Recorded, ServiceError> {
+            let mut recorded = None;
+            for group in groups {
+                recorded = Some(self.record(db, Some(group))?);
+            }
+            match recorded {
+                Some(recorded) => Ok(recorded),
+                None => self.record(db, None),
+            }
+        }
+    }
+}
 
 #[derive(Clone)]
-pub struct Database;
+pub struct Database {
+    pool: PgPool,
+    blocking: BlockingPool,
+    notifier: Notifier,
+    webhooks: Webhooks,
+    streams: EventStreams,
+}
+
 impl Database {
-    pub fn create_user(&self, username: &str) -> Result<String, tide::Error> {
-        let mut db = DB::connect();  
-        db.create_user(username)
+    pub fn new(config: &Config) -> Result<Self, PoolError> {
+        let manager = ConnectionManager::<PgConnection>::new(&config.database_url);
+        let pool = Pool::builder()
+            .max_size(config.pool_size)
+            .build(manager)?;
+        Ok(Self::from_pool(pool, config.pool_size)
+            .with_url_policy(UrlPolicy::new(&config.webhook_allowed_hosts)))
+    }
+
+    /// A service on an existing pool of `pool_size` connections. Webhooks are only
+    /// delivered to public addresses unless [`Database::with_url_policy`] allows more.
+    pub fn from_pool(pool: PgPool, pool_size: u32) -> Self {
+        // One thread per connection: no thread waits for a connection while
+        // another one sits idle.
+        let blocking = BlockingPool::new(pool_size as usize);
+        let webhooks = Self::webhooks(&pool, UrlPolicy::default());
+        Self { pool, blocking, notifier: Notifier::default(), webhooks, streams: EventStreams::default() }
+    }
+
+    /// Delivers webhooks to the hosts `policy` allows from now on.
+    pub fn with_url_policy(self, policy: UrlPolicy) -> Self {
+        let webhooks = Self::webhooks(&self.pool, policy);
+        Self { webhooks, ..self }
+    }
+
+    fn webhooks(pool: &PgPool, policy: UrlPolicy) -> Webhooks {
+        let log_pool = pool.clone();
+        Webhooks::new(policy, move |attempt| {
+            let result = log_pool.get()
+                .map_err(|e| e.to_string())
+                .and_then(|conn| DB { conn }.log_delivery(&attempt).map_err(|e| e.to_string()));
+            if let Err(e) = result {
+                log::warn!("Cannot record delivery of webhook {}: {e}", attempt.webhook_id);
+            }
+        })
+    }
+
+    /// Runs `f` on the blocking thread pool, so that its queries don't block the
+    /// async executor and a slow one doesn't hold up other requests.
+    pub async fn run<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Database) -> Result<T, ServiceError> + Send + 'static,
+    ) -> Result<T, ServiceError> {
+        let database = self.clone();
+        self.blocking.run(move || f(&database)).await.unwrap_or_else(|| {
+            log::error!("Database operation panicked");
+            Err(ServiceError::Internal)
+        })
+    }
+
+    /// Sends notification emails through `notifier` from now on.
+    pub fn with_notifier(self, notifier: Notifier) -> Self {
+        Self { notifier, ..self }
+    }
+
+    /// Fails if the database has migrations applied that this binary doesn't know,
+    /// i.e. the schema was created by a newer version of the service.
+    pub fn check_schema_version(&self) -> diesel::migration::Result<()> {
+        let mut db = DB { conn: self.pool.get()? };
+        let unknown = db.unknown_applied_migrations()?;
+        match unknown.is_empty() {
+            true => Ok(()),
+            false => Err(format!(
+                "database schema is newer than this binary, unknown migrations: {}",
+                unknown.join(", ")
+            ).into()),
+        }
+    }
+
+    /// Applies pending migrations and returns the versions that were applied.
+    pub fn run_migrations(&self) -> diesel::migration::Result<Vec<String>> {
+        self.check_schema_version()?;
+        let mut db = DB { conn: self.pool.get()? };
+        db.run_pending_migrations()
+    }
+
+    /// Exports all users, groups, memberships and assignments as one consistent
+    /// snapshot. The secrets of webhooks are left out unless `include_secrets`.
+    pub fn export_data(&self, include_secrets: bool) -> Result<Dump, DumpError> {
+        log::info!("Exporting data (secrets: {include_secrets})");
+
+        let mut db = DB { conn: self.pool.get().map_err(DumpError::Connection)? };
+        db.export(include_secrets)
+    }
+
+    /// Imports a dump in a single transaction. Without `force` the database must
+    /// be empty; with it, all existing data is deleted first.
+    pub fn import_data(&self, dump: &Dump, force: bool) -> Result<ImportSummary, DumpError> {
+        log::info!("Importing data (force: {force})");
+
+        dump.validate()?;
+        let mut db = DB { conn: self.pool.get().map_err(DumpError::Connection)? };
+        db.import(dump, force)
+    }
+
+    fn connect(&self) -> Result<DB, ServiceError> {
+        self.pool.get()
+            .map(|conn| DB { conn })
+            .map_err(|e| {
+                log::error!("Cannot get database connection: {e}");
+                ServiceError::Internal
+            })
+    }
+
+    pub fn check_readiness(&self) -> ReadinessResponse {
+        log::debug!("Checking readiness");
+
+        let conn = match self.pool.get_timeout(READINESS_TIMEOUT) {
+            Ok(conn) => conn,
+            Err(e) => {
+                log::warn!("Database is not reachable: {e}");
+                return ReadinessResponse::new(CheckStatus::Failed, CheckStatus::Failed);
+            }
+        };
+        let mut db = DB { conn };
+        let database = match db.ping() {
+            Ok(_) => CheckStatus::Ok,
+            Err(e) => {
+                log::warn!("Database ping failed: {e}");
+                CheckStatus::Failed
+            }
+        };
+        let migrations = match db.has_pending_migrations() {
+            Ok(false) => CheckStatus::Ok,
+            Ok(true) => CheckStatus::Pending,
+            Err(e) => {
+                log::warn!("Cannot check migrations: {e}");
+                CheckStatus::Failed
+            }
+        };
+        ReadinessResponse::new(database, migrations)
+    }
+
+    /// Refreshes the group and membership gauges reported on `/metrics`.
+    pub fn refresh_metrics(&self) -> Result<(), ServiceError> {
+        let mut db = self.connect()?;
+        let (open, closed) = db.count_groups()
+            .map_err(|_| ServiceError::Internal)?;
+        let members = db.count_members()
+            .map_err(|_| ServiceError::Internal)?;
+        metrics().set_group_counts(open, closed, members);
+        Ok(())
+    }
+
+    pub fn create_user(&self, username: &str, email: Option<&str>) -> Result<StatusResponse, ServiceError> {
+        if email.is_some_and(|email| !notifications::is_valid_email(email)) {
+            return Err(ServiceError::BadRequest("Invalid email address".to_string()));
+        }
+        let mut db = self.connect()?;  
+        db.create_user(username, email)
             .map_err(|e| match e {
                 DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
-                    errors::error_same_name("User".to_string())
+                    ServiceError::UserExists(username.to_string())
                 }
-                _ => errors::error_internal_server(),
+                _ => ServiceError::Internal,
             })?;
-        Ok(StatusCode::Ok.to_string())
+        Ok(StatusResponse::ok())
+    }
+
+    /// Sets or, with `None`, removes the address notifications are sent to.
+    pub fn set_user_email(&self, username: &str, email: Option<&str>) -> Result<StatusResponse, ServiceError> {
+        log::debug!("Setting email of user {username}");
+
+        if email.is_some_and(|email| !notifications::is_valid_email(email)) {
+            return Err(ServiceError::BadRequest("Invalid email address".to_string()));
+        }
+        let mut db = self.connect()?;
+        let mut user = db.get_user(username)
+            .map_err(|_| ServiceError::UserNotFound(username.to_string()))?;
+        user.email = email.map(str::to_string);
+        db.update_user_email(&user)
+            .map_err(|_| ServiceError::Internal)?;
+        Ok(StatusResponse::ok())
     }
 
     pub fn create_group_by_user(
         &self,
         username: &str,
         group_name: &str,
-    ) -> Result<String, tide::Error> {
-        log::debug!("Creating group {group_name} by user {username}");
+        settings: &GroupSettings,
+    ) -> Result<StatusResponse, ServiceError> {
+        log::debug!("Creating group {group_name} by user {username} with {settings:?}");
 
-        let mut db = DB::connect();
-        let user = db.get_user(username)
-            .map_err(|_| errors::error_bad_request("User not found".to_string()))?;
-        db.create_group(group_name)
-            .map_err(|e| match e {
-                DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
-                    errors::error_same_name("Group".to_string())
-                }
-                _ => errors::error_bad_request("Error".to_string()),
-            })?;
-        let group = db.get_group(group_name)
-            .map_err(|_| errors::error_bad_request("Group not found".to_string()))?;
-        db.create_member(&user, &group, Role::Admin)
-            .map_err(|e| match e {
-                DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
-                    errors::error_same_name("Member".to_string())
-                }
-                _ => errors::error_internal_server(),
+        self.audited(AuditAction::CreateGroup, Some(username), None, Some(group_name), |db, audit| {
+            settings.check()?;
+            let user = db.get_user(username)
+                .map_err(|_| ServiceError::UserNotFound(username.to_string()))?;
+            // The group and its first admin are created together, so no group is
+            // left without an admin if adding the member fails.
+            let (group, recorded) = db.transaction(|db| {
+                db.create_group(group_name, settings)
+                    .map_err(|e| match e {
+                        DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                            ServiceError::GroupExists(group_name.to_string())
+                        }
+                        _ => ServiceError::Internal,
+                    })?;
+                let group = db.get_group(group_name)
+                    .map_err(|_| ServiceError::GroupNotFound(group_name.to_string()))?;
+                audit.resolved(&group);
+                db.create_member(&user, &group, Role::Admin, None)
+                    .map_err(|_| ServiceError::Internal)?;
+                let recorded = audit.record(db, Some(&group))?;
+                Ok((group, recorded))
             })?;
-        Ok(StatusCode::Ok.to_string())
+            self.publish(db, &group, GroupEvent::GroupCreated);
+            Ok((StatusResponse::ok(), recorded))
+        })
     }
 
     pub fn add_user_to_group(
         &self,
         username: &str,
         group_name: &str,
-    ) -> Result<String, tide::Error> {
+    ) -> Result<StatusResponse, ServiceError> {
         log::debug!("Adding user {username} to group {group_name}");
 
-        let mut db = DB::connect();
-        let user = db.get_user(username)
-            .map_err(|_| errors::error_bad_request("User not found".to_string()))?;
-        let group = db.get_group(group_name)
-            .map_err(|_| errors::error_bad_request("Group not found".to_string()))?;
-        match group.is_close {
-            true => Err(errors::error_method_not_allowed("Group close".to_string())),
-            false => {
-                db.create_member(&user, &group, Role::Member)
+        self.audited(AuditAction::JoinGroup, Some(username), None, Some(group_name), |db, audit| {
+            let user = db.get_user(username)
+                .map_err(|_| ServiceError::UserNotFound(username.to_string()))?;
+            let lock = db.get_group(group_name)
+                .map(|group| GroupLock::to_add_members(&group))
+                .map_err(|_| ServiceError::GroupNotFound(group_name.to_string()))?;
+            // The lock keeps the draw from starting until the new member is in.
+            let (group, recorded) = db.transaction(|db| {
+                let group = db.lock_group(group_name, lock)
+                    .map_err(|_| ServiceError::GroupNotFound(group_name.to_string()))?;
+                audit.resolved(&group);
+                if group.is_close {
+                    return Err(ServiceError::GroupClosed(group_name.to_string()));
+                }
+                if let Some(capacity) = group.capacity {
+                    let members = db.count_group_members(&group)
+                        .map_err(|_| ServiceError::Internal)?;
+                    if members >= i64::from(capacity) {
+                        return Err(ServiceError::GroupFull { group_name: group_name.to_string(), capacity });
+                    }
+                }
+                db.create_member(&user, &group, Role::Member, None)
                     .map_err(|e| match e {
                         DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
-                            errors::error_same_name("Member".to_string())
+                            ServiceError::AlreadyMember {
+                                username: username.to_string(),
+                                group_name: group_name.to_string(),
+                            }
                         }
-                        _ => errors::error_internal_server(),
+                        _ => ServiceError::Internal,
                     })?;
-                Ok(StatusCode::Ok.to_string())
-            }
-        }
+                let recorded = audit.record(db, Some(&group))?;
+                Ok((group, recorded))
+            })?;
+            self.notifier.notify(&user, Notification::AddedToGroup { group_name });
+            self.publish(db, &group, GroupEvent::MemberJoined { username: user.name });
+            Ok((StatusResponse::ok(), recorded))
+        })
     }
 
     pub fn get_recipient_name(
         &self,
         santa_name: &str,
         group_name: &str,
-    ) -> Result<String, tide::Error> {
+    ) -> Result<RecipientResponse, ServiceError> {
         log::debug!("Getting recipient for santa {santa_name} in group {group_name}");
 
-        let mut db = DB::connect();
+        let mut db = self.connect()?;
         let santa = db.get_user(santa_name)
-            .map_err(|_| errors::error_bad_request("User not found".to_string()))?;
+            .map_err(|_| ServiceError::UserNotFound(santa_name.to_string()))?;
         let group = db.get_group(group_name)
-            .map_err(|_| errors::error_bad_request("Group not found".to_string()))?;
+            .map_err(|_| ServiceError::GroupNotFound(group_name.to_string()))?;
         match group.is_close {
             true => {
                 let recipient = db.get_santa_recipient(&group, &santa)
-                    .map_err(|_| errors::error_internal_server())?;
-                Ok(json!({"recipient_name": recipient.name}).to_string())
+                    .map_err(|e| match e {
+                        diesel::result::Error::NotFound => ServiceError::NotAMember {
+                            username: santa_name.to_string(),
+                            group_name: group_name.to_string(),
+                        },
+                        _ => ServiceError::Internal,
+                    })?;
+                Ok(RecipientResponse { recipient_name: recipient.name })
             }
-            false => Err(errors::error_too_early("It's too early to recognize recipient".to_string())),
+            false => Err(ServiceError::DrawNotStarted(group_name.to_string())),
         }
     }
 
@@ -106,139 +448,792 @@ impl Database {
         &self,
         username: &str,
         group_name: &str
-    ) -> Result<String, tide::Error> {
+    ) -> Result<StatusResponse, ServiceError> {
         log::debug!("Deleting group {group_name} by Admin");
 
-        let mut db = DB::connect();
-        let user = db.get_user(username)
-            .map_err(|_| errors::error_bad_request("User not found".to_string()))?;
-        let group = db.get_group(group_name)
-            .map_err(|_| errors::error_bad_request("Group not found".to_string()))?;
-        let member = db.get_member(&user, &group)
-            .map_err(|_| errors::error_bad_request("User is not a member of this group".to_string()))?;
-        match member.urole.eq(&Role::Admin) {
-            true => {
-                let group_for_delete = db.get_group(group_name)
-                    .map_err(|_| errors::error_bad_request("Group not found".to_string()))?;
-                db.delete_group(group_for_delete)
-                    .map_err(|_| errors::error_internal_server())?;
-                Ok(StatusCode::Ok.to_string())
+        self.audited(AuditAction::DeleteGroup, Some(username), None, Some(group_name), |db, audit| {
+            let (group_id, removed, webhooks, recorded) = db.transaction(|db| {
+                let group = Self::lock_group_as(db, audit, group_name, GroupLock::Update)?;
+                let removed = db.get_member_users(&group)
+                    .map_err(|_| ServiceError::Internal)?;
+                // Deleting the group deletes its webhooks too, so they are loaded
+                // first to be told about it; their attempts are not logged.
+                let webhooks = db.get_webhooks_for_group(&group)
+                    .map_err(|_| ServiceError::Internal)?;
+                let group_id = group.id;
+                let recorded = audit.record(db, Some(&group))?;
+                db.delete_group(group)
+                    .map_err(|_| ServiceError::Internal)?;
+                Ok((group_id, removed, webhooks, recorded))
+            })?;
+            for (_, user) in removed {
+                self.notifier.notify(&user, Notification::RemovedFromGroup { group_name });
             }
-            false => Err(errors::error_method_not_allowed("Not enough rights".to_string())),
-        }
+            self.publish_to(webhooks, group_id, group_name, GroupEvent::GroupDeleted);
+            self.streams.close(group_id);
+            Ok((StatusResponse::ok(), recorded))
+        })
     }
 
     pub fn close_group(
         &self,
         username: &str,
         group_name: &str,
-    ) -> Result<String, tide::Error> {
+    ) -> Result<StatusResponse, ServiceError> {
         log::debug!("Try to start secret Santa by {username} in group {group_name}");
 
-        let mut db = DB::connect();
-        let user = db.get_user(username)
-            .map_err(|_| errors::error_bad_request("User not found".to_string()))?;
-        let mut group = db.get_group(group_name)
-            .map_err(|_| errors::error_bad_request("Group not found".to_string()))?;
-        let admin_member = db.get_member(&user, &group)
-            .map_err(|_| errors::error_bad_request("User is not a member of this group".to_string()))?;
-        if admin_member.urole != crate::models::Role::Admin || group.is_close {
-            return Err(errors::error_method_not_allowed("Not enough rights".to_string()));
+        self.audited(AuditAction::CloseGroup, Some(username), None, Some(group_name), |db, audit| {
+            let recorded = self.draw(db, audit, group_name)?;
+            Ok((StatusResponse::ok(), recorded))
+        })
+    }
+
+    /// Runs the draw on behalf of an operator, without requiring an admin of the group.
+    pub fn force_close_group(&self, group_name: &str) -> Result<StatusResponse, ServiceError> {
+        log::info!("Force closing group {group_name}");
+
+        self.audited(AuditAction::CloseGroup, None, None, Some(group_name), |db, audit| {
+            let recorded = self.draw(db, audit, group_name)?;
+            Ok((StatusResponse::ok(), recorded))
+        })
+    }
+
+    /// Assigns every member of an open group a recipient, closes the group and
+    /// tells every member whom they give a present to. The draw is run by an
+    /// admin of the group or, without an actor of `audit`, by an operator.
+    fn draw(
+        &self,
+        db: &mut DB<impl PgConn>,
+        audit: &Audit,
+        group_name: &str,
+    ) -> Result<Recorded, ServiceError> {
+        let (group, users, recorded) = db.transaction(|db| {
+            let mut group = Self::lock_group_as(db, audit, group_name, GroupLock::Update)?;
+            if group.is_close {
+                return Err(ServiceError::GroupClosed(group.gname));
+            }
+
+            let mut members = db.get_member_users(&group)
+                .map_err(|_| ServiceError::Internal)?;
+            if members.len() < 3 {
+                return Err(ServiceError::NotEnoughMembers {
+                    group_name: group.gname,
+                    members: members.len(),
+                });
+            }
+            let exclusions: HashSet<(i32, i32)> = db.get_exclusions(&group)
+                .map_err(|_| ServiceError::Internal)?
+                .into_iter()
+                .flat_map(|e| [(e.user_id, e.excluded_user_id), (e.excluded_user_id, e.user_id)])
+                .collect();
+            // Every member gives a present to the next one, the last to the first.
+            let is_allowed = |members: &[(Member, User)]| {
+                members.iter()
+                    .zip(members.iter().cycle().skip(1))
+                    .all(|((_, santa), (_, recipient))| !exclusions.contains(&(santa.id, recipient.id)))
+            };
+
+            let mut rng = thread_rng();
+            members.shuffle(&mut rng);
+            let mut attempts = 1;
+            while !is_allowed(&members) {
+                if attempts == DRAW_ATTEMPTS {
+                    return Err(ServiceError::DrawImpossible(group.gname));
+                }
+                members.shuffle(&mut rng);
+                attempts += 1;
+            }
+
+            let users: Vec<User> = members.into_iter().map(|(_, user)| user).collect();
+            let assignments: Vec<(&User, &User)> = users.iter()
+                .zip(users.iter().cycle().skip(1))
+                .collect();
+            db.set_santas(&group, &assignments)
+                .map_err(|_| ServiceError::Internal)?;
+
+            group.is_close = true;
+            db.update_group(&group)
+                .map_err(|_| ServiceError::Internal)?;
+            let recorded = audit.record(db, Some(&group))?;
+            Ok((group, users, recorded))
+        })?;
+
+        for (santa, recipient) in users.iter().zip(users.iter().cycle().skip(1)) {
+            self.notifier.notify(santa, Notification::DrawComplete {
+                group_name: &group.gname,
+                recipient: &recipient.name,
+            });
         }
+        self.publish(db, &group, GroupEvent::DrawCompleted { members: users.len() });
+        Ok(recorded)
+    }
+
+    pub fn import_members_by_admin(
+        &self,
+        username: &str,
+        group_name: &str,
+        csv: &str,
+    ) -> Result<ImportMembersResponse, ServiceError> {
+        log::debug!("Importing members into group {group_name} by user {username}");
+
+        self.audited(AuditAction::ImportMembers, Some(username), None, Some(group_name), |db, audit| {
+            let rows = member_import::parse(csv)?;
+            self.import_rows(db, audit, group_name, rows)
+        })
+    }
+
+    /// Imports members on behalf of an operator, without requiring an admin of the group.
+    pub fn import_members(&self, group_name: &str, csv: &str) -> Result<ImportMembersResponse, ServiceError> {
+        log::info!("Importing members into group {group_name}");
+
+        self.audited(AuditAction::ImportMembers, None, None, Some(group_name), |db, audit| {
+            let rows = member_import::parse(csv)?;
+            self.import_rows(db, audit, group_name, rows)
+        })
+    }
+
+    /// Registers missing users and adds every accepted row to an open group, with
+    /// its wishlist and exclusions, in one transaction. Rejected rows change nothing
+    /// and are only reported. The import is run by an admin of the group or,
+    /// without an actor of `audit`, by an operator.
+    fn import_rows(
+        &self,
+        db: &mut DB<impl PgConn>,
+        audit: &Audit,
+        group_name: &str,
+        mut rows: Vec<ImportRow>,
+    ) -> Result<(ImportMembersResponse, Recorded), ServiceError> {
+        let lock = db.get_group(group_name)
+            .map(|group| GroupLock::to_add_members(&group))
+            .map_err(|_| ServiceError::GroupNotFound(group_name.to_string()))?;
+        let (group, response, added, recorded) = db.transaction(|db| {
+            let group = Self::lock_group_as(db, audit, group_name, lock)?;
+            if group.is_close {
+                return Err(ServiceError::GroupClosed(group.gname));
+            }
+
+            let mut user_ids: HashMap<String, i32> = db.get_member_users(&group)
+                .map_err(|_| ServiceError::Internal)?
+                .into_iter()
+                .map(|(_, user)| (user.name, user.id))
+                .collect();
+            member_import::check(&mut rows, |name| user_ids.contains_key(name));
+            let mut free = group.capacity.map(|capacity| (capacity as usize).saturating_sub(user_ids.len()));
+
+            let mut reports = Vec::with_capacity(rows.len());
+            let mut added = Vec::new();
+            let mut exclusions = Vec::new();
+            for row in rows {
+                let (status, message) = match (row.username.as_deref(), row.outcome) {
+                    (_, Err(Rejection::Skipped(message))) => (ImportRowStatus::Skipped, Some(message)),
+                    (_, Err(Rejection::Failed(message))) => (ImportRowStatus::Failed, Some(message)),
+                    (None, Ok(_)) => (ImportRowStatus::Failed, Some("Username is empty".to_string())),
+                    (Some(_), Ok(_)) if free == Some(0) => {
+                        (ImportRowStatus::Failed, Some("The group is full".to_string()))
+                    }
+                    (Some(username), Ok(member)) => {
+                        // Users may register or join the group while the import
+                        // runs, so both inserts tolerate rows that appeared since
+                        // the check above.
+                        let status = match db.create_missing_user(username) {
+                            Ok(0) => ImportRowStatus::Added,
+                            Ok(_) => ImportRowStatus::Created,
+                            Err(_) => return Err(ServiceError::Internal),
+                        };
+                        let user = db.get_user(username)
+                            .map_err(|_| ServiceError::Internal)?;
+                        let inserted = db.create_missing_member(&user, &group, member.wishlist.as_deref())
+                            .map_err(|_| ServiceError::Internal)?;
+                        match inserted {
+                            0 => (ImportRowStatus::Skipped, Some("Already a member of the group".to_string())),
+                            _ => {
+                                user_ids.insert(user.name.clone(), user.id);
+                                free = free.map(|free| free - 1);
+                                exclusions.push((user.id, member.exclude));
+                                added.push(user);
+                                (status, None)
+                            }
+                        }
+                    }
+                };
+                reports.push(ImportRowReport {
+                    line: row.line,
+                    username: row.username,
+                    status,
+                    message,
+                });
+            }
 
-        let mut members = db.get_members(&group)?;
-        if members.len() < 3 {
-            return Err(errors::error_method_not_allowed("Not enough members".to_string(),));
+            for (user_id, partners) in exclusions {
+                for partner in partners {
+                    if let Some(&partner_id) = user_ids.get(&partner) {
+                        db.create_exclusion(&group, user_id, partner_id)
+                            .map_err(|_| ServiceError::Internal)?;
+                    }
+                }
+            }
+
+            let response = ImportMembersResponse {
+                group_name: group.gname.clone(),
+                rows: reports,
+            };
+            let recorded = audit.record(db, Some(&group))?;
+            Ok((group, response, added, recorded))
+        })?;
+
+        for user in added {
+            self.notifier.notify(&user, Notification::AddedToGroup { group_name: &group.gname });
+            self.publish(db, &group, GroupEvent::MemberJoined { username: user.name });
         }
-        let mut rng = thread_rng();
-        members.shuffle(&mut rng);
+        Ok((response, recorded))
+    }
 
-        let mut cur_santa = db.get_user_from_member(members.get(members.len() - 1).unwrap())
-            .map_err(|_| errors::error_internal_server())?;
-        let mut cur_recipient = db.get_user_from_member(members.get(0).unwrap())
-            .map_err(|_| errors::error_internal_server())?;
-        db.set_santa(&group, &cur_santa, &cur_recipient)
-            .map_err(|_| errors::error_internal_server())?;
+    /// Lets admins export the assignments of a closed group; until then they stay secret.
+    pub fn reveal_assignments(
+        &self,
+        username: &str,
+        group_name: &str,
+    ) -> Result<StatusResponse, ServiceError> {
+        log::debug!("Revealing assignments of group {group_name} by user {username}");
 
-        cur_santa = cur_recipient;
-        for i in 1..members.len() - 1 {  
-            cur_recipient = db.get_user_from_member(members.get(i).unwrap())
-                .map_err(|_| errors::error_internal_server())?;
-            db.set_santa(&group, &cur_santa, &cur_recipient)
-                .map_err(|_| errors::error_internal_server())?;
-            cur_santa = cur_recipient;
+        self.audited(AuditAction::RevealAssignments, Some(username), None, Some(group_name), |db, audit| {
+            let recorded = db.transaction(|db| {
+                let mut group = Self::lock_group_as(db, audit, group_name, GroupLock::Update)?;
+                if !group.is_close {
+                    return Err(ServiceError::DrawNotStarted(group.gname));
+                }
+                group.is_revealed = true;
+                db.update_group(&group)
+                    .map_err(|_| ServiceError::Internal)?;
+                audit.record(db, Some(&group))
+            })?;
+            Ok((StatusResponse::ok(), recorded))
+        })
+    }
+
+    pub fn get_assignments(
+        &self,
+        username: &str,
+        group_name: &str,
+    ) -> Result<AssignmentsResponse, ServiceError> {
+        log::debug!("Exporting assignments of group {group_name} by user {username}");
+
+        let mut db = self.connect()?;
+        let group = Self::get_group_as_admin(&mut db, username, group_name)?;
+        if !group.is_close {
+            return Err(ServiceError::DrawNotStarted(group.gname));
+        }
+        if !group.is_revealed {
+            return Err(ServiceError::NotRevealed(group.gname));
         }
+        let assignments = db.get_assignments(&group)
+            .map_err(|_| ServiceError::Internal)?;
 
-        group.is_close = true;
-        db.update_group(&group)
-            .map_err(|_| errors::error_internal_server())?;
+        Ok(AssignmentsResponse {
+            group_name: group.gname,
+            assignments: assignments.into_iter()
+                .map(|(santa, recipient)| AssignmentResponse { santa, recipient })
+                .collect(),
+        })
+    }
 
-        Ok(StatusCode::Ok.to_string())
+    /// Sends `event` to the event streams of `group`, to its webhooks and to those
+    /// registered for every group. Failures are only logged: they must not undo the
+    /// change that caused the event.
+    fn publish(&self, db: &mut DB<impl PgConn>, group: &Group, event: GroupEvent) {
+        let webhooks = db.get_webhooks_for_group(group).unwrap_or_else(|e| {
+            log::error!("Cannot load webhooks of group {}: {e}", group.id);
+            Vec::new()
+        });
+        self.publish_to(webhooks, group.id, &group.gname, event);
     }
 
-    pub fn get_group_members(
+    fn publish_to(&self, webhooks: Vec<Webhook>, group_id: i32, group_name: &str, event: GroupEvent) {
+        let envelope = EventEnvelope { group_name, timestamp: Utc::now(), event: &event };
+        let body = match serde_json::to_string(&envelope) {
+            Ok(body) => body,
+            Err(e) => {
+                log::error!("Cannot serialize {} event: {e}", event.name());
+                return;
+            }
+        };
+        self.streams.broadcast(group_id, event.name(), &body);
+        for webhook in webhooks {
+            let Some(secret) = webhook.secret else {
+                log::warn!("Webhook {} needs a new secret, not delivering {}", webhook.id, event.name());
+                continue;
+            };
+            self.webhooks.deliver(Delivery {
+                webhook_id: webhook.id,
+                url: webhook.url,
+                secret,
+                event: event.name(),
+                body: body.clone(),
+            });
+        }
+    }
+
+    fn validate_webhook(&self, url: &str, secret: &str) -> Result<(), ServiceError> {
+        self.webhooks.check_url(url).map_err(ServiceError::BadRequest)?;
+        if secret.is_empty() || secret.len() > webhooks::MAX_SECRET_LEN {
+            return Err(ServiceError::BadRequest(format!(
+                "Webhook secret must have 1 to {} characters",
+                webhooks::MAX_SECRET_LEN
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn add_webhook(
         &self,
         username: &str,
         group_name: &str,
-    ) -> Result<String, tide::Error> {
-        log::debug!("Getting members of group {group_name} by user {username}");
+        url: &str,
+        secret: &str,
+    ) -> Result<WebhookResponse, ServiceError> {
+        log::debug!("Adding webhook to group {group_name} by user {username}");
+
+        self.audited(AuditAction::AddWebhook, Some(username), Some(url), Some(group_name), |db, audit| {
+            self.validate_webhook(url, secret)?;
+            db.transaction(|db| {
+                let group = Self::lock_group_as(db, audit, group_name, GroupLock::Share)?;
+                let webhook = db.create_webhook(Some(&group), url, secret)
+                    .map_err(|_| ServiceError::Internal)?;
+                let recorded = audit.record(db, Some(&group))?;
+                Ok((WebhookResponse {
+                    id: webhook.id,
+                    group_name: Some(group.gname),
+                    url: webhook.url,
+                    needs_secret: false,
+                }, recorded))
+            })
+        })
+    }
+
+    pub fn remove_webhook(
+        &self,
+        username: &str,
+        group_name: &str,
+        webhook_id: i32,
+    ) -> Result<StatusResponse, ServiceError> {
+        log::debug!("Removing webhook {webhook_id} from group {group_name} by user {username}");
+
+        self.audited(AuditAction::RemoveWebhook, Some(username), Some(&webhook_id.to_string()), Some(group_name), |db, audit| {
+            let recorded = db.transaction(|db| {
+                let group = Self::lock_group_as(db, audit, group_name, GroupLock::Share)?;
+                let webhook = db.get_webhook(webhook_id)
+                    .ok()
+                    .filter(|webhook| webhook.group_id == Some(group.id))
+                    .ok_or(ServiceError::WebhookNotFound(webhook_id))?;
+                db.delete_webhook(webhook)
+                    .map_err(|_| ServiceError::Internal)?;
+                audit.record(db, Some(&group))
+            })?;
+            Ok((StatusResponse::ok(), recorded))
+        })
+    }
+
+    pub fn get_webhooks(&self, username: &str, group_name: &str) -> Result<WebhooksResponse, ServiceError> {
+        log::debug!("Getting webhooks of group {group_name} by user {username}");
 
-        let mut db = DB::connect();
+        let mut db = self.connect()?;
+        let group = Self::get_group_as_admin(&mut db, username, group_name)?;
+        let webhooks = db.get_group_webhooks(&group)
+            .map_err(|_| ServiceError::Internal)?;
+        Ok(WebhooksResponse {
+            webhooks: webhooks.into_iter()
+                .map(|webhook| WebhookResponse {
+                    id: webhook.id,
+                    group_name: Some(group.gname.clone()),
+                    url: webhook.url,
+                    needs_secret: webhook.secret.is_none(),
+                })
+                .collect(),
+        })
+    }
+
+    /// Delivery log of a group's own webhooks, newest first.
+    pub fn get_webhook_deliveries(
+        &self,
+        username: &str,
+        group_name: &str,
+        before: Option<i32>,
+        limit: Option<i64>,
+    ) -> Result<WebhookDeliveriesResponse, ServiceError> {
+        log::debug!("Getting webhook deliveries of group {group_name} by user {username}");
+
+        let limit = Self::page_size(limit)?;
+        let mut db = self.connect()?;
+        let group = Self::get_group_as_admin(&mut db, username, group_name)?;
+        let deliveries = db.get_webhook_deliveries(&group, before, limit)
+            .map_err(|_| ServiceError::Internal)?;
+
+        let next_before = match deliveries.len() as i64 == limit {
+            true => deliveries.last().map(|(delivery, _)| delivery.id),
+            false => None,
+        };
+        Ok(WebhookDeliveriesResponse {
+            deliveries: deliveries.into_iter()
+                .map(|(delivery, url)| WebhookDeliveryResponse {
+                    id: delivery.id,
+                    webhook_id: delivery.webhook_id,
+                    url,
+                    event: delivery.event,
+                    attempt: delivery.attempt,
+                    status_code: delivery.status_code,
+                    error: delivery.error,
+                    success: delivery.success,
+                    created_at: delivery.created_at,
+                })
+                .collect(),
+            next_before,
+        })
+    }
+
+    /// Audit log of a group, newest first.
+    pub fn get_audit_log(
+        &self,
+        username: &str,
+        group_name: &str,
+        before: Option<i32>,
+        limit: Option<i64>,
+    ) -> Result<AuditLogResponse, ServiceError> {
+        log::debug!("Getting audit log of group {group_name} by user {username}");
+
+        let limit = Self::page_size(limit)?;
+        let mut db = self.connect()?;
+        let group = Self::get_group_as_admin(&mut db, username, group_name)?;
+        let entries = db.get_audit_log(&group, before, limit)
+            .map_err(|_| ServiceError::Internal)?;
+
+        let next_before = match entries.len() as i64 == limit {
+            true => entries.last().map(|entry| entry.id),
+            false => None,
+        };
+        Ok(AuditLogResponse {
+            entries: entries.into_iter()
+                .map(|entry| AuditEntryResponse {
+                    id: entry.id,
+                    actor: entry.actor,
+                    action: entry.action,
+                    target: entry.target,
+                    outcome: entry.outcome,
+                    created_at: entry.created_at,
+                })
+                .collect(),
+            next_before,
+        })
+    }
+
+    fn page_size(limit: Option<i64>) -> Result<i64, ServiceError> {
+        let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        match (1..=MAX_PAGE_SIZE).contains(&limit) {
+            true => Ok(limit),
+            false => Err(ServiceError::BadRequest(format!("limit must be between 1 and {MAX_PAGE_SIZE}"))),
+        }
+    }
+
+    /// Runs a mutating operation and records who did what in which group, and
+    /// whether it succeeded or with which error code it failed. An operation
+    /// without `actor` was run by an operator.
+    ///
+    /// The operation records its success itself with [`Audit::record`], inside
+    /// its transaction, and returns the [`Recorded`] proof with its result. A
+    /// failure is recorded here, on the operation's connection, in the log of
+    /// the group the operation resolved, and only if the caller is a member of
+    /// that group or an operator, so that anyone else can't fill a group's log
+    /// with failed attempts.
+    fn audited<T>(
+        &self,
+        action: AuditAction,
+        actor: Option<&str>,
+        target: Option<&str>,
+        group_name: Option<&str>,
+        operation: impl FnOnce(&mut DB, &Audit) -> Result<(T, Recorded), ServiceError>,
+    ) -> Result<T, ServiceError> {
+        let audit = Audit::new(action, actor, target);
+        let mut db = self.connect()?;
+        match operation(&mut db, &audit) {
+            Ok((value, _recorded)) => Ok(value),
+            Err(e) => {
+                if let Err(log_error) = Self::record_failure(&mut db, &audit, group_name, &e) {
+                    log::error!("Cannot record failed {} in the audit log: {log_error}", action.name());
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn record_failure(
+        db: &mut DB<impl PgConn>,
+        audit: &Audit,
+        group_name: Option<&str>,
+        error: &ServiceError,
+    ) -> Result<(), ServiceError> {
+        let group = audit.group();
+        if let Some(actor) = audit.actor {
+            let is_member = match &group {
+                Some((group_id, _)) => db.is_member(actor, *group_id)?,
+                None => false,
+            };
+            if !is_member {
+                return Ok(());
+            }
+        }
+        let (group_id, group_name) = match &group {
+            Some((group_id, name)) => (Some(*group_id), Some(name.as_str())),
+            None => (None, group_name),
+        };
+        db.log_audit_entry(&audit.entry(group_id, group_name, error.code()))?;
+        Ok(())
+    }
+
+    /// Registers a webhook on behalf of an operator, for one group or, without
+    /// a group, for the events of every group.
+    pub fn register_webhook(
+        &self,
+        group_name: Option<&str>,
+        url: &str,
+        secret: &str,
+    ) -> Result<WebhookResponse, ServiceError> {
+        log::info!("Registering webhook for {}", group_name.unwrap_or("all groups"));
+
+        self.audited(AuditAction::AddWebhook, None, Some(url), group_name, |db, audit| {
+            self.validate_webhook(url, secret)?;
+            db.transaction(|db| {
+                let group = match group_name {
+                    Some(group_name) => Some(
+                        db.get_group(group_name)
+                            .map_err(|_| ServiceError::GroupNotFound(group_name.to_string()))?,
+                    ),
+                    None => None,
+                };
+                if let Some(group) = &group {
+                    audit.resolved(group);
+                }
+                let webhook = db.create_webhook(group.as_ref(), url, secret)
+                    .map_err(|_| ServiceError::Internal)?;
+                let recorded = audit.record(db, group.as_ref())?;
+                Ok((WebhookResponse {
+                    id: webhook.id,
+                    group_name: group.map(|group| group.gname),
+                    url: webhook.url,
+                    needs_secret: false,
+                }, recorded))
+            })
+        })
+    }
+
+    pub fn get_all_webhooks(&self) -> Result<WebhooksResponse, ServiceError> {
+        log::debug!("Getting all webhooks");
+
+        let mut db = self.connect()?;
+        let webhooks = db.get_all_webhooks()
+            .map_err(|_| ServiceError::Internal)?;
+        Ok(WebhooksResponse {
+            webhooks: webhooks.into_iter()
+                .map(|(webhook, group)| WebhookResponse {
+                    id: webhook.id,
+                    group_name: group.map(|group| group.gname),
+                    url: webhook.url,
+                    needs_secret: webhook.secret.is_none(),
+                })
+                .collect(),
+        })
+    }
+
+    /// Deletes any webhook on behalf of an operator.
+    pub fn unregister_webhook(&self, webhook_id: i32) -> Result<StatusResponse, ServiceError> {
+        log::info!("Unregistering webhook {webhook_id}");
+
+        let target = webhook_id.to_string();
+        self.audited(AuditAction::RemoveWebhook, None, Some(&target), None, |db, audit| {
+            let recorded = db.transaction(|db| {
+                let webhook = db.get_webhook(webhook_id)
+                    .map_err(|_| ServiceError::WebhookNotFound(webhook_id))?;
+                let group = webhook.group_id
+                    .map(|group_id| db.get_group_by_id(group_id))
+                    .transpose()
+                    .map_err(|_| ServiceError::Internal)?;
+                db.delete_webhook(webhook)
+                    .map_err(|_| ServiceError::Internal)?;
+                audit.record(db, group.as_ref())
+            })?;
+            Ok((StatusResponse::ok(), recorded))
+        })
+    }
+
+    /// Looks up a group on behalf of one of its admins.
+    fn get_group_as_admin(db: &mut DB<impl PgConn>, username: &str, group_name: &str) -> Result<Group, ServiceError> {
         let user = db.get_user(username)
-            .map_err(|_| errors::error_bad_request("User not found".to_string()))?;
+            .map_err(|_| ServiceError::UserNotFound(username.to_string()))?;
         let group = db.get_group(group_name)
-            .map_err(|_| errors::error_bad_request("Group not found".to_string()))?;
-        let member = db.get_member(&user, &group)
-            .map_err(|_| errors::error_bad_request("User is not a member of this group".to_string()))?;
+            .map_err(|_| ServiceError::GroupNotFound(group_name.to_string()))?;
+        Self::check_admin(db, &user, &group)?;
+        Ok(group)
+    }
 
+    /// Looks up a group and locks it until the end of the transaction, on behalf
+    /// of the actor of `audit`, who must be one of its admins, or of an operator.
+    fn lock_group_as(
+        db: &mut DB<impl PgConn>,
+        audit: &Audit,
+        group_name: &str,
+        lock: GroupLock,
+    ) -> Result<Group, ServiceError> {
+        let user = audit.actor
+            .map(|username| db.get_user(username)
+                .map_err(|_| ServiceError::UserNotFound(username.to_string())))
+            .transpose()?;
+        let group = db.lock_group(group_name, lock)
+            .map_err(|_| ServiceError::GroupNotFound(group_name.to_string()))?;
+        audit.resolved(&group);
+        if let Some(user) = user {
+            Self::check_admin(db, &user, &group)?;
+        }
+        Ok(group)
+    }
+
+    fn check_admin(db: &mut DB<impl PgConn>, user: &User, group: &Group) -> Result<(), ServiceError> {
+        let member = db.get_member(user, group)
+            .map_err(|_| ServiceError::NotAMember {
+                username: user.name.clone(),
+                group_name: group.gname.clone(),
+            })?;
         match member.urole {
-            Role::Admin => {
-                let members = db.get_members(&group)
-                    .map_err(|_| errors::error_bad_request("User is not a member of this group".to_string()))?;
-
-                let mut users = Vec::with_capacity(members.len());
-                for member in members {
-                    let user = db.get_user_from_member(&member)
-                        .map_err(|_| errors::error_bad_request("User not found".to_string()))?;
-                    users.push(user.name);
-                }
+            Role::Admin => Ok(()),
+            Role::Member => Err(ServiceError::NotAnAdmin {
+                username: user.name.clone(),
+                group_name: group.gname.clone(),
+            }),
+        }
+    }
 
-                Ok(json!({group_name: users}).to_string())
-            }
-            Role::Member => Err(errors::error_method_not_allowed("Not enough rights".to_string())),
+    /// Follows the events of a group; only its members may subscribe.
+    pub fn subscribe_to_group_events(
+        &self,
+        username: &str,
+        group_name: &str,
+    ) -> Result<Receiver<StreamEvent>, ServiceError> {
+        log::debug!("Subscribing user {username} to events of group {group_name}");
+
+        let mut db = self.connect()?;
+        let user = db.get_user(username)
+            .map_err(|_| ServiceError::UserNotFound(username.to_string()))?;
+        let group = db.get_group(group_name)
+            .map_err(|_| ServiceError::GroupNotFound(group_name.to_string()))?;
+        db.get_member(&user, &group)
+            .map_err(|_| ServiceError::NotAMember {
+                username: username.to_string(),
+                group_name: group_name.to_string(),
+            })?;
+        Ok(self.streams.subscribe(group.id, user.id))
+    }
+
+    /// Members of a group with their roles, for whoever the group's
+    /// visibility policy admits.
+    pub fn get_group_members(
+        &self,
+        username: &str,
+        group_name: &str,
+    ) -> Result<GroupMembersResponse, ServiceError> {
+        log::debug!("Getting members of group {group_name} by user {username}");
+
+        let mut db = self.connect()?;
+        let user = db.get_user(username)
+            .map_err(|_| ServiceError::UserNotFound(username.to_string()))?;
+        let group = db.get_group(group_name)
+            .map_err(|_| ServiceError::GroupNotFound(group_name.to_string()))?;
+        let role = db.get_member(&user, &group).ok().map(|member| member.urole);
+        match (group.member_visibility, role) {
+            (MemberVisibility::Public, _)
+            | (MemberVisibility::Members, Some(_))
+            | (MemberVisibility::Admins, Some(Role::Admin)) => {}
+            (_, None) => return Err(ServiceError::NotAMember {
+                username: username.to_string(),
+                group_name: group_name.to_string(),
+            }),
+            (_, Some(Role::Member)) => return Err(ServiceError::NotAnAdmin {
+                username: username.to_string(),
+                group_name: group_name.to_string(),
+            }),
         }
+
+        let members = db.get_member_users(&group)
+            .map_err(|_| ServiceError::Internal)?;
+
+        Ok(GroupMembersResponse {
+            group_name: group_name.to_string(),
+            members: members.into_iter().map(MemberResponse::from).collect(),
+        })
+    }
+
+    pub fn set_member_visibility(
+        &self,
+        username: &str,
+        group_name: &str,
+        visibility: MemberVisibility,
+    ) -> Result<StatusResponse, ServiceError> {
+        log::debug!("Setting member visibility of group {group_name} to {visibility:?} by user {username}");
+
+        self.audited(AuditAction::SetMemberVisibility, Some(username), None, Some(group_name), |db, audit| {
+            let recorded = db.transaction(|db| {
+                let mut group = Self::lock_group_as(db, audit, group_name, GroupLock::Update)?;
+                group.member_visibility = visibility;
+                db.update_group(&group)
+                    .map_err(|_| ServiceError::Internal)?;
+                audit.record(db, Some(&group))
+            })?;
+            Ok((StatusResponse::ok(), recorded))
+        })
     }
 
     pub fn revoke_rights_of_admin(
         &self,
         username: &str,
         group_name: &str
-    ) -> Result<String, tide::Error> {
+    ) -> Result<StatusResponse, ServiceError> {
         log::debug!("Try to revoke rights by Admin of group {group_name}");
 
-        let mut db = DB::connect();
-        let user = db.get_user(username)
-            .map_err(|_| errors::error_bad_request("User not found".to_string()))?;
-        let group = db.get_group(group_name)
-            .map_err(|_| errors::error_bad_request("Group not found".to_string()))?;
-        let member = db.get_member(&user, &group)
-            .map_err(|_| errors::error_bad_request("User is not a member of this group".to_string()))?;
-        match member.urole.eq(&Role::Admin) {
-            true => {
-                let number_of_admins = db.count_admins(&group)
-                    .map_err(|_| errors::error_internal_server())?;
-                if number_of_admins > 1 {
-                    let changed_member = member.set_role(Role::Member);
-                    db.update_member(changed_member)
-                        .map_err(|_| errors::error_internal_server())?;
-                    Ok(StatusCode::Ok.to_string())
+        self.audited(AuditAction::RevokeAdmin, Some(username), Some(username), Some(group_name), |db, audit| {
+            let user = db.get_user(username)
+                .map_err(|_| ServiceError::UserNotFound(username.to_string()))?;
+            // Admins revoking their rights at the same time wait for each other,
+            // so the last one of them is refused.
+            let (group, recorded) = db.transaction(|db| {
+                let group = db.lock_group(group_name, GroupLock::Update)
+                    .map_err(|_| ServiceError::GroupNotFound(group_name.to_string()))?;
+                audit.resolved(&group);
+                let member = db.get_member(&user, &group)
+                    .map_err(|_| ServiceError::NotAMember {
+                        username: username.to_string(),
+                        group_name: group_name.to_string(),
+                    })?;
+                if member.urole != Role::Admin {
+                    return Err(ServiceError::NotAnAdmin {
+                        username: username.to_string(),
+                        group_name: group_name.to_string(),
+                    });
                 }
-                else {
-                    Err(errors::error_bad_request("You are the only admin".to_string()))
+                let number_of_admins = db.count_admins(&group)
+                    .map_err(|_| ServiceError::Internal)?;
+                if number_of_admins <= 1 {
+                    return Err(ServiceError::LastAdmin {
+                        username: username.to_string(),
+                        group_name: group_name.to_string(),
+                    });
                 }
-            }
-            false => Err(errors::error_method_not_allowed("Not enough rights".to_string())),
-        }
+                db.update_member(member.set_role(Role::Member))
+                    .map_err(|_| ServiceError::Internal)?;
+                let recorded = audit.record(db, Some(&group))?;
+                Ok((group, recorded))
+            })?;
+            self.publish(db, &group, GroupEvent::AdminChanged {
+                username: user.name,
+                is_admin: false,
+            });
+            Ok((StatusResponse::ok(), recorded))
+        })
     }
 
     pub fn add_admin_to_group(
@@ -246,111 +1241,609 @@ impl Database {
         username: &str,
         new_admin: &str,
         group_name: &str
-    ) -> Result<String, tide::Error> {
+    ) -> Result<StatusResponse, ServiceError> {
         log::debug!("Creating user {new_admin} as admin in group {group_name}");
 
-        let mut db = DB::connect();
-        let group = db.get_group(group_name)
-            .map_err(|_| errors::error_bad_request("Group not found".to_string()))?;
-        let user_setter = db.get_user(username)
-            .map_err(|_| errors::error_bad_request("User not found".to_string()))?;
-        let setter_member = db.get_member(&user_setter, &group)
-            .map_err(|_| errors::error_bad_request("User is not a member of this group".to_string()))?;
-        match setter_member.urole.eq(&Role::Admin) {
-            true => {
+        self.audited(AuditAction::AddAdmin, Some(username), Some(new_admin), Some(group_name), |db, audit| {
+            // The share lock makes an admin revoking their own rights at the same
+            // time wait, so neither change is decided on a stale list of admins.
+            let (group, user_new_admin, recorded) = db.transaction(|db| {
+                let group = Self::lock_group_as(db, audit, group_name, GroupLock::Share)?;
                 let user_new_admin = db.get_user(new_admin)
-                    .map_err(|_| errors::error_bad_request("User not found".to_string()))?;
+                    .map_err(|_| ServiceError::UserNotFound(new_admin.to_string()))?;
                 let new_admin_member = db.get_member(&user_new_admin, &group)
-                    .map_err(|_| errors::error_bad_request("User is not a member of this group".to_string()))?;
-                let changed_member = new_admin_member.set_role(Role::Admin);
-                db.update_member(changed_member)
-                    .map_err(|_| errors::error_internal_server())?;
-                Ok(StatusCode::Ok.to_string())
-            }
-            false => Err(errors::error_method_not_allowed("Not enough rights".to_string())),
-        }
+                    .map_err(|_| ServiceError::NotAMember {
+                        username: new_admin.to_string(),
+                        group_name: group_name.to_string(),
+                    })?;
+                db.update_member(new_admin_member.set_role(Role::Admin))
+                    .map_err(|_| ServiceError::Internal)?;
+                let recorded = audit.record(db, Some(&group))?;
+                Ok((group, user_new_admin, recorded))
+            })?;
+            self.publish(db, &group, GroupEvent::AdminChanged {
+                username: user_new_admin.name,
+                is_admin: true,
+            });
+            Ok((StatusResponse::ok(), recorded))
+        })
     }
 
-    pub fn get_open_groups(&self) -> Result<String, tide::Error> {
+    /// One page of the open groups, optionally narrowed down by a name search and
+    /// the groups' settings.
+    pub fn get_open_groups(&self, query: &GroupsQuery) -> Result<GroupsResponse, ServiceError> {
         log::debug!("Getting list of opened groups");
 
-        let mut db = DB::connect();
-        let groups = db.get_open_groups()
-            .map_err(|_| errors::error_internal_server())?;
-        Ok(json!({"groups": groups}).to_string())
+        let limit = Self::page_size(query.limit)?;
+        query.check()?;
+        let after = query.cursor.as_deref()
+            .map(|cursor| GroupCursor::decode(cursor, query.sort, query.order))
+            .transpose()?;
+        let mut db = self.connect()?;
+        let groups = db.get_open_groups(query, after.as_ref(), limit)
+            .map_err(|_| ServiceError::Internal)?;
+
+        let next_cursor = match groups.len() as i64 == limit {
+            true => groups.last().map(|group| GroupCursor::after(group, query.sort, query.order).encode()),
+            false => None,
+        };
+        Ok(GroupsResponse {
+            groups: groups.into_iter().map(GroupResponse::from).collect(),
+            next_cursor,
+        })
+    }
+
+    pub fn get_all_groups(&self) -> Result<GroupsResponse, ServiceError> {
+        log::debug!("Getting list of all groups");
+
+        let mut db = self.connect()?;
+        let groups = db.get_all_groups()
+            .map_err(|_| ServiceError::Internal)?;
+        Ok(GroupsResponse {
+            groups: groups.into_iter().map(GroupResponse::from).collect(),
+            next_cursor: None,
+        })
+    }
+
+    /// Groups the user is a member of, by name, with their role and whether they
+    /// can look up their recipient.
+    pub fn get_user_groups(&self, username: &str) -> Result<MyGroupsResponse, ServiceError> {
+        log::debug!("Getting groups of user {username}");
+
+        let mut db = self.connect()?;
+        let user = db.get_user(username)
+            .map_err(|_| ServiceError::UserNotFound(username.to_string()))?;
+        let mut memberships = db.get_memberships(&user)
+            .map_err(|_| ServiceError::Internal)?;
+        memberships.sort_by(|(_, a), (_, b)| a.gname.cmp(&b.gname));
+        let group_ids: Vec<i32> = memberships.iter().map(|(_, group)| group.id).collect();
+        let member_counts: HashMap<i32, i64> = db.count_members_by_group(&group_ids)
+            .map_err(|_| ServiceError::Internal)?
+            .into_iter()
+            .collect();
+        let drawn: HashSet<i32> = db.get_santa_group_ids(&user)
+            .map_err(|_| ServiceError::Internal)?
+            .into_iter()
+            .collect();
+
+        Ok(MyGroupsResponse {
+            groups: memberships.into_iter()
+                .map(|(member, group)| MyGroupResponse {
+                    member_count: member_counts.get(&group.id).copied().unwrap_or_default(),
+                    has_recipient: drawn.contains(&group.id),
+                    group_name: group.gname,
+                    role: member.urole.into(),
+                    is_closed: group.is_close,
+                })
+                .collect(),
+        })
+    }
+
+    /// Operator view of a group: its state and members with their roles, but not the assignments.
+    pub fn inspect_group(&self, group_name: &str) -> Result<GroupDetailsResponse, ServiceError> {
+        log::debug!("Inspecting group {group_name}");
+
+        let mut db = self.connect()?;
+        let group = db.get_group(group_name)
+            .map_err(|_| ServiceError::GroupNotFound(group_name.to_string()))?;
+        let members = db.get_member_users(&group)
+            .map_err(|_| ServiceError::Internal)?;
+
+        Ok(GroupDetailsResponse {
+            group_name: group.gname,
+            is_closed: group.is_close,
+            member_visibility: group.member_visibility.into(),
+            created_at: group.created_at,
+            updated_at: group.updated_at,
+            members: members.into_iter().map(MemberResponse::from).collect(),
+        })
+    }
+
+    /// Deletes a user unless that would break a group: users can't leave closed
+    /// groups, and an open group can't lose its last admin.
+    pub fn delete_user(&self, username: &str) -> Result<StatusResponse, ServiceError> {
+        log::info!("Deleting user {username}");
+
+        // Recorded in the log of every group the user leaves or, if they are
+        // in none, without a group.
+        self.audited(AuditAction::DeleteUser, None, Some(username), None, |db, audit| {
+            let user = db.get_user(username)
+                .map_err(|_| ServiceError::UserNotFound(username.to_string()))?;
+            let user_id = user.id;
+            let (groups, recorded) = db.transaction(|db| {
+                let mut memberships = db.get_memberships(&user).map_err(|_| ServiceError::Internal)?;
+                // Locked in the order of their ids, so that deleting two users of the
+                // same groups at once can't deadlock.
+                memberships.sort_by_key(|(_, group)| group.id);
+                let mut groups = Vec::with_capacity(memberships.len());
+                for (member, group) in memberships {
+                    let group = match db.lock_group(&group.gname, GroupLock::Update) {
+                        Ok(group) => group,
+                        Err(diesel::result::Error::NotFound) => continue,
+                        Err(_) => return Err(ServiceError::Internal),
+                    };
+                    audit.resolved(&group);
+                    if group.is_close {
+                        return Err(ServiceError::GroupClosed(group.gname));
+                    }
+                    if member.urole == Role::Admin {
+                        let number_of_admins = db.count_admins(&group)
+                            .map_err(|_| ServiceError::Internal)?;
+                        if number_of_admins <= 1 {
+                            return Err(ServiceError::LastAdmin {
+                                username: username.to_string(),
+                                group_name: group.gname,
+                            });
+                        }
+                    }
+                    groups.push(group);
+                }
+                db.delete_user(user)
+                    .map_err(|_| ServiceError::Internal)?;
+                let recorded = audit.record_each(db, &groups)?;
+                Ok((groups, recorded))
+            })?;
+            for group in &groups {
+                self.streams.unsubscribe(group.id, user_id);
+                self.publish(db, group, GroupEvent::MemberLeft { username: username.to_string() });
+            }
+            Ok((StatusResponse::ok(), recorded))
+        })
     }
 }
 
-struct DB {
-    conn: PgConnection
+/// Queries on one connection: one from the pool or, inside
+/// [`DB::transaction`], the connection of the transaction.
+struct DB<C = PooledConnection<ConnectionManager<PgConnection>>> {
+    conn: C,
 }
 
-impl DB {
-    fn connect() -> Self {
-        log::debug!("Connect enter point");
-        dotenv().ok();
-        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-        Self {
-            conn: PgConnection::establish(&database_url)
-                .expect(&format!("Error connecting to {}", &database_url))
-        }
+/// A connection [`DB`] can run its queries on.
+trait PgConn: DerefMut<Target = PgConnection> {}
+
+impl<C: DerefMut<Target = PgConnection>> PgConn for C {}
+
+impl<C: PgConn> DB<C> {
+    /// Runs `f` in a transaction that is committed if it succeeds and rolled back
+    /// otherwise; nested calls use savepoints.
+    fn transaction<T>(
+        &mut self,
+        f: impl FnOnce(&mut DB<&mut PgConnection>) -> Result<T, ServiceError>,
+    ) -> Result<T, ServiceError> {
+        self.conn.transaction(|conn| f(&mut DB { conn }))
+    }
+
+    fn export(&mut self, include_secrets: bool) -> Result<Dump, DumpError> {
+        let _timer = metrics().db_timer("export");
+        use crate::schema::{exclusions, members, santas, sgroups, users, webhooks};
+
+        self.conn.build_transaction().repeatable_read().read_only().run(|conn| {
+            let all_users: Vec<User> = users::table.order(users::id).load(conn)?;
+            let all_groups: Vec<Group> = sgroups::table.order(sgroups::id).load(conn)?;
+            let all_members: Vec<Member> = members::table.order(members::id).load(conn)?;
+            let all_santas: Vec<Santa> = santas::table.order(santas::id).load(conn)?;
+            let all_exclusions: Vec<Exclusion> = exclusions::table.order(exclusions::id).load(conn)?;
+            let all_webhooks: Vec<Webhook> = webhooks::table.order(webhooks::id).load(conn)?;
+
+            Ok(Dump {
+                version: DUMP_VERSION,
+                users: all_users.into_iter()
+                    .map(|user| DumpUser {
+                        id: user.id,
+                        name: user.name,
+                        email: user.email,
+                        timestamps: DumpTimestamps::new(user.created_at, user.updated_at),
+                    })
+                    .collect(),
+                groups: all_groups.into_iter()
+                    .map(|group| DumpGroup {
+                        id: group.id,
+                        name: group.gname,
+                        is_closed: group.is_close,
+                        is_revealed: group.is_revealed,
+                        capacity: group.capacity,
+                        draw_date: group.draw_date,
+                        budget: group.budget,
+                        member_visibility: group.member_visibility.into(),
+                        timestamps: DumpTimestamps::new(group.created_at, group.updated_at),
+                    })
+                    .collect(),
+                members: all_members.into_iter()
+                    .map(|member| DumpMember {
+                        user_id: member.user_id,
+                        group_id: member.group_id,
+                        role: member.urole.into(),
+                        wishlist: member.wishlist,
+                        timestamps: DumpTimestamps::new(member.created_at, member.updated_at),
+                    })
+                    .collect(),
+                santas: all_santas.into_iter()
+                    .map(|santa| DumpSanta {
+                        group_id: santa.group_id,
+                        santa_id: santa.santa_id,
+                        recipient_id: santa.recipient_id,
+                        timestamps: DumpTimestamps::new(santa.created_at, santa.updated_at),
+                    })
+                    .collect(),
+                exclusions: all_exclusions.into_iter()
+                    .map(|exclusion| DumpExclusion {
+                        group_id: exclusion.group_id,
+                        user_id: exclusion.user_id,
+                        excluded_user_id: exclusion.excluded_user_id,
+                    })
+                    .collect(),
+                webhooks: all_webhooks.into_iter()
+                    .map(|webhook| DumpWebhook {
+                        group_id: webhook.group_id,
+                        url: webhook.url,
+                        secret: webhook.secret.filter(|_| include_secrets),
+                    })
+                    .collect(),
+            })
+        })
+    }
+
+    fn import(&mut self, dump: &Dump, force: bool) -> Result<ImportSummary, DumpError> {
+        let _timer = metrics().db_timer("import");
+        use crate::schema::{exclusions, members, santas, sgroups, users, webhook_deliveries, webhooks};
+
+        self.conn.build_transaction().serializable().run(|conn| {
+            let existing_users: i64 = users::table.count().get_result(conn)?;
+            let existing_groups: i64 = sgroups::table.count().get_result(conn)?;
+            let existing_webhooks: i64 = webhooks::table.count().get_result(conn)?;
+            if existing_users + existing_groups + existing_webhooks > 0 {
+                if !force {
+                    return Err(DumpError::NotEmpty);
+                }
+                log::warn!("Deleting existing data before import");
+                diesel::delete(webhook_deliveries::table).execute(conn)?;
+                diesel::delete(webhooks::table).execute(conn)?;
+                diesel::delete(exclusions::table).execute(conn)?;
+                diesel::delete(santas::table).execute(conn)?;
+                diesel::delete(members::table).execute(conn)?;
+                diesel::delete(sgroups::table).execute(conn)?;
+                diesel::delete(users::table).execute(conn)?;
+            }
+
+            let now = Utc::now();
+            let mut user_ids = HashMap::with_capacity(dump.users.len());
+            for user in &dump.users {
+                let new_id: i32 = diesel::insert_into(users::table)
+                    .values((
+                        NewUser { name: &user.name, email: user.email.as_deref() },
+                        users::created_at.eq(user.timestamps.created_at.unwrap_or(now)),
+                        users::updated_at.eq(user.timestamps.updated_at.unwrap_or(now)),
+                    ))
+                    .returning(users::id)
+                    .get_result(conn)?;
+                user_ids.insert(user.id, new_id);
+            }
+
+            let mut group_ids = HashMap::with_capacity(dump.groups.len());
+            for group in &dump.groups {
+                let new_id: i32 = diesel::insert_into(sgroups::table)
+                    .values((
+                        sgroups::gname.eq(&group.name),
+                        sgroups::is_close.eq(group.is_closed),
+                        sgroups::is_revealed.eq(group.is_revealed),
+                        sgroups::capacity.eq(group.capacity),
+                        sgroups::draw_date.eq(group.draw_date),
+                        sgroups::budget.eq(group.budget),
+                        sgroups::member_visibility.eq(MemberVisibility::from(group.member_visibility)),
+                        sgroups::created_at.eq(group.timestamps.created_at.unwrap_or(now)),
+                        sgroups::updated_at.eq(group.timestamps.updated_at.unwrap_or(now)),
+                    ))
+                    .returning(sgroups::id)
+                    .get_result(conn)?;
+                group_ids.insert(group.id, new_id);
+            }
+
+            for member in &dump.members {
+                diesel::insert_into(members::table)
+                    .values((
+                        NewMember {
+                            user_id: user_ids[&member.user_id],
+                            group_id: group_ids[&member.group_id],
+                            urole: member.role.into(),
+                            wishlist: member.wishlist.as_deref(),
+                        },
+                        members::created_at.eq(member.timestamps.created_at.unwrap_or(now)),
+                        members::updated_at.eq(member.timestamps.updated_at.unwrap_or(now)),
+                    ))
+                    .execute(conn)?;
+            }
+
+            for santa in &dump.santas {
+                diesel::insert_into(santas::table)
+                    .values((
+                        NewSanta {
+                            group_id: group_ids[&santa.group_id],
+                            santa_id: user_ids[&santa.santa_id],
+                            recipient_id: user_ids[&santa.recipient_id],
+                        },
+                        santas::created_at.eq(santa.timestamps.created_at.unwrap_or(now)),
+                        santas::updated_at.eq(santa.timestamps.updated_at.unwrap_or(now)),
+                    ))
+                    .execute(conn)?;
+            }
+
+            for exclusion in &dump.exclusions {
+                diesel::insert_into(exclusions::table)
+                    .values(NewExclusion {
+                        group_id: group_ids[&exclusion.group_id],
+                        user_id: user_ids[&exclusion.user_id],
+                        excluded_user_id: user_ids[&exclusion.excluded_user_id],
+                    })
+                    .execute(conn)?;
+            }
+
+            for webhook in &dump.webhooks {
+                diesel::insert_into(webhooks::table)
+                    .values(NewWebhook {
+                        group_id: webhook.group_id.map(|group_id| group_ids[&group_id]),
+                        url: &webhook.url,
+                        secret: webhook.secret.as_deref(),
+                    })
+                    .execute(conn)?;
+            }
+
+            Ok(ImportSummary {
+                users: dump.users.len(),
+                groups: dump.groups.len(),
+                members: dump.members.len(),
+                santas: dump.santas.len(),
+                exclusions: dump.exclusions.len(),
+                webhooks: dump.webhooks.len(),
+            })
+        })
+    }
+
+    fn ping(&mut self) -> Result<usize, diesel::result::Error> {
+        let _timer = metrics().db_timer("ping");
+        diesel::sql_query("SELECT 1").execute(&mut *self.conn)
+    }
+
+    fn has_pending_migrations(&mut self) -> diesel::migration::Result<bool> {
+        let _timer = metrics().db_timer("has_pending_migrations");
+        self.conn.has_pending_migration(MIGRATIONS)
+    }
+
+    fn unknown_applied_migrations(&mut self) -> diesel::migration::Result<Vec<String>> {
+        let known: Vec<MigrationVersion<'static>> = MigrationSource::<Pg>::migrations(&MIGRATIONS)?
+            .iter()
+            .map(|migration| migration.name().version().as_owned())
+            .collect();
+        let applied = self.conn.applied_migrations()?;
+        Ok(applied.into_iter()
+            .filter(|version| !known.contains(version))
+            .map(|version| version.to_string())
+            .collect())
     }
 
-    fn create_user(&mut self, username: &str) -> Result<usize, diesel::result::Error> {
+    fn run_pending_migrations(&mut self) -> diesel::migration::Result<Vec<String>> {
+        log::info!("Running pending migrations");
+        let applied = self.conn.run_pending_migrations(MIGRATIONS)?;
+        Ok(applied.into_iter().map(|version| version.to_string()).collect())
+    }
+
+    fn create_user(&mut self, username: &str, user_email: Option<&str>) -> Result<usize, diesel::result::Error> {
+        let _timer = metrics().db_timer("create_user");
         log::debug!("Create user {username}");
-        let new_user = NewUser { name: username };
+        let new_user = NewUser { name: username, email: user_email };
 
         use crate::schema::users::dsl::*;
         log::debug!("User {username} created");
-        diesel::insert_into(users).values(&new_user).execute(&mut self.conn)
+        diesel::insert_into(users).values(&new_user).execute(&mut *self.conn)
+    }
+
+    /// Registers a user without an email unless the name is taken; returns how
+    /// many users were created.
+    fn create_missing_user(&mut self, username: &str) -> Result<usize, diesel::result::Error> {
+        let _timer = metrics().db_timer("create_missing_user");
+        log::debug!("Create user {username} if missing");
+        let new_user = NewUser { name: username, email: None };
+
+        use crate::schema::users::dsl::*;
+        diesel::insert_into(users)
+            .values(&new_user)
+            .on_conflict_do_nothing()
+            .execute(&mut *self.conn)
     }
 
     fn get_user(&mut self, username: &str) -> Result<User, diesel::result::Error> {
+        let _timer = metrics().db_timer("get_user");
         log::debug!("Try to find user {username}");
 
         use crate::schema::users::dsl::*;
-        users.filter(name.eq(username)).first(&mut self.conn)
+        users.filter(name.eq(username)).first(&mut *self.conn)
     }
 
-    fn create_group(&mut self, group_name: &str) -> Result<usize, diesel::result::Error> {
+    fn update_user_email(&mut self, user: &User) -> Result<usize, diesel::result::Error> {
+        let _timer = metrics().db_timer("update_user_email");
+        log::debug!("Update email of user with id {}", user.id);
+
+        use crate::schema::users::dsl::*;
+        diesel::update(users.filter(id.eq(user.id)))
+            .set(email.eq(&user.email))
+            .execute(&mut *self.conn)
+    }
+
+    fn delete_user(&mut self, user: User) -> Result<usize, diesel::result::Error> {
+        let _timer = metrics().db_timer("delete_user");
+        log::debug!("Delete user with id {}", user.id);
+
+        use crate::schema::users::dsl::*;
+        diesel::delete(users.filter(id.eq(user.id)))
+            .execute(&mut *self.conn)
+    }
+
+    fn create_group(&mut self, group_name: &str, settings: &GroupSettings) -> Result<usize, diesel::result::Error> {
+        let _timer = metrics().db_timer("create_group");
         log::debug!("Create group {}", group_name);
-        let new_group = NewGroup { gname: group_name };
+        let new_group = NewGroup {
+            gname: group_name,
+            capacity: settings.capacity,
+            draw_date: settings.draw_date,
+            budget: settings.budget,
+        };
 
         use crate::schema::sgroups::dsl::*;
         diesel::insert_into(sgroups)
             .values(&new_group)
-            .execute(&mut self.conn)
+            .execute(&mut *self.conn)
     }
 
     fn get_group(&mut self, group_name: &str) -> Result<Group, diesel::result::Error> {
+        let _timer = metrics().db_timer("get_group");
         log::debug!("Try to find group {group_name}");
 
         use crate::schema::sgroups::dsl::*;
-        sgroups.filter(gname.eq(group_name)).first(&mut self.conn)
+        sgroups.filter(gname.eq(group_name)).first(&mut *self.conn)
     }
 
-    fn get_open_groups(&mut self) -> Result<Vec<Group>, diesel::result::Error> {
-        log::debug!("Get all groups");
+    fn get_group_by_id(&mut self, group_id: i32) -> Result<Group, diesel::result::Error> {
+        let _timer = metrics().db_timer("get_group_by_id");
+        log::debug!("Try to find group {group_id}");
 
         use crate::schema::sgroups::dsl::*;
-        sgroups.filter(is_close.eq(false)).load(&mut self.conn)
+        sgroups.find(group_id).first(&mut *self.conn)
+    }
+
+    /// Reads a group and locks its row until the end of the transaction.
+    fn lock_group(&mut self, group_name: &str, lock: GroupLock) -> Result<Group, diesel::result::Error> {
+        let _timer = metrics().db_timer("lock_group");
+        log::debug!("Lock group {group_name} ({lock:?})");
+
+        use crate::schema::sgroups::dsl::*;
+        let query = sgroups.filter(gname.eq(group_name));
+        match lock {
+            GroupLock::Share => query.for_share().first(&mut *self.conn),
+            GroupLock::Update => query.for_update().first(&mut *self.conn),
+        }
+    }
+
+    fn get_open_groups(
+        &mut self,
+        filter: &GroupsQuery,
+        after: Option<&GroupCursor>,
+        limit: i64,
+    ) -> Result<Vec<Group>, diesel::result::Error> {
+        let _timer = metrics().db_timer("get_open_groups");
+        let (sort, order) = (filter.sort, filter.order);
+        log::debug!("Get open groups by {sort:?} {order:?} matching {:?}", filter.search);
+
+        use crate::schema::members;
+        use crate::schema::sgroups::dsl::*;
+        let mut query = sgroups.filter(is_close.eq(false)).limit(limit).into_boxed();
+        if let Some(search) = &filter.search {
+            query = query.filter(gname.ilike(pagination::contains_pattern(search)));
+        }
+        if let Some(has_space) = filter.has_space {
+            let member_count = members::table
+                .filter(members::group_id.eq(id))
+                .count()
+                .single_value();
+            let free = capacity.is_null().or(capacity.cast::<Nullable<BigInt>>().gt(member_count));
+            query = match has_space {
+                true => query.filter(free),
+                false => query.filter(diesel::dsl::not(free)),
+            };
+        }
+        if let Some(from) = filter.draw_from {
+            query = query.filter(draw_date.ge(from));
+        }
+        if let Some(until) = filter.draw_until {
+            query = query.filter(draw_date.le(until));
+        }
+        if let Some(min) = filter.min_budget {
+            query = query.filter(budget.ge(min));
+        }
+        if let Some(max) = filter.max_budget {
+            query = query.filter(budget.le(max));
+        }
+        // Names are unique; timestamps are not, so ties are broken by id.
+        if let Some(after) = after {
+            let at = after.at.unwrap_or_default();
+            let name = after.name.clone().unwrap_or_default();
+            query = match (sort, order) {
+                (GroupSort::Name, SortOrder::Asc) => query.filter(gname.gt(name)),
+                (GroupSort::Name, SortOrder::Desc) => query.filter(gname.lt(name)),
+                (GroupSort::CreatedAt, SortOrder::Asc) => query
+                    .filter(created_at.gt(at).or(created_at.eq(at).and(id.gt(after.id)))),
+                (GroupSort::CreatedAt, SortOrder::Desc) => query
+                    .filter(created_at.lt(at).or(created_at.eq(at).and(id.lt(after.id)))),
+                (GroupSort::UpdatedAt, SortOrder::Asc) => query
+                    .filter(updated_at.gt(at).or(updated_at.eq(at).and(id.gt(after.id)))),
+                (GroupSort::UpdatedAt, SortOrder::Desc) => query
+                    .filter(updated_at.lt(at).or(updated_at.eq(at).and(id.lt(after.id)))),
+            };
+        }
+        let query = match (sort, order) {
+            (GroupSort::Name, SortOrder::Asc) => query.order(gname.asc()),
+            (GroupSort::Name, SortOrder::Desc) => query.order(gname.desc()),
+            (GroupSort::CreatedAt, SortOrder::Asc) => query.order((created_at.asc(), id.asc())),
+            (GroupSort::CreatedAt, SortOrder::Desc) => query.order((created_at.desc(), id.desc())),
+            (GroupSort::UpdatedAt, SortOrder::Asc) => query.order((updated_at.asc(), id.asc())),
+            (GroupSort::UpdatedAt, SortOrder::Desc) => query.order((updated_at.desc(), id.desc())),
+        };
+        query.load(&mut *self.conn)
+    }
+
+    fn count_groups(&mut self) -> Result<(i64, i64), diesel::result::Error> {
+        let _timer = metrics().db_timer("count_groups");
+        log::debug!("Count open and closed groups");
+
+        use crate::schema::sgroups::dsl::*;
+        let open: i64 = sgroups.filter(is_close.eq(false)).count().get_result(&mut *self.conn)?;
+        let closed: i64 = sgroups.filter(is_close.eq(true)).count().get_result(&mut *self.conn)?;
+        Ok((open, closed))
+    }
+
+    fn get_all_groups(&mut self) -> Result<Vec<Group>, diesel::result::Error> {
+        let _timer = metrics().db_timer("get_all_groups");
+        log::debug!("Get all groups including closed ones");
+
+        use crate::schema::sgroups::dsl::*;
+        sgroups.order(gname).load(&mut *self.conn)
     }
 
     fn update_group(&mut self, group: &Group) -> Result<usize, diesel::result::Error> {
-        log::debug!("Update group with id {} to {:?}", group.id, group);
+        let _timer = metrics().db_timer("update_group");
+        log::debug!("Update group with id {} (closed: {})", group.id, group.is_close);
 
         use crate::schema::sgroups::dsl::*;
         diesel::update(sgroups.filter(id.eq(group.id)))
             .set(group)
-            .execute(&mut self.conn)
+            .execute(&mut *self.conn)
     }
 
     fn delete_group(&mut self, group: Group) -> Result<usize, diesel::result::Error> {
-        log::debug!("Delete group {group:?}");
+        let _timer = metrics().db_timer("delete_group");
+        log::debug!("Delete group with id {}", group.id);
 
         use crate::schema::sgroups::dsl::*;
         diesel::delete(sgroups.filter(id.eq(group.id)))
-            .execute(&mut self.conn)
+            .execute(&mut *self.conn)
     }
 
     fn create_member(
@@ -358,89 +1851,394 @@ impl DB {
         user: &User,
         group: &Group,
         role: Role,
+        member_wishlist: Option<&str>,
     ) -> Result<usize, diesel::result::Error> {
-        log::debug!("Add member {user:?} to group {group:?}");
+        let _timer = metrics().db_timer("create_member");
+        log::debug!("Add user {} to group {}", user.id, group.id);
 
         let new_group_member = NewMember {
             user_id: user.id,
             group_id: group.id,
             urole: role,
+            wishlist: member_wishlist,
         };
         log::debug!("New group member created");
         use crate::schema::members::dsl::*;
         diesel::insert_into(members)
             .values(new_group_member)
-            .execute(&mut self.conn)
+            .execute(&mut *self.conn)
+    }
+
+    /// Adds a user to a group as a member unless they already belong to it;
+    /// returns how many members were created.
+    fn create_missing_member(
+        &mut self,
+        user: &User,
+        group: &Group,
+        member_wishlist: Option<&str>,
+    ) -> Result<usize, diesel::result::Error> {
+        let _timer = metrics().db_timer("create_missing_member");
+        log::debug!("Add user {} to group {} if missing", user.id, group.id);
+
+        let new_group_member = NewMember {
+            user_id: user.id,
+            group_id: group.id,
+            urole: Role::Member,
+            wishlist: member_wishlist,
+        };
+        use crate::schema::members::dsl::*;
+        diesel::insert_into(members)
+            .values(new_group_member)
+            .on_conflict_do_nothing()
+            .execute(&mut *self.conn)
     }
 
     fn get_member(&mut self, user: &User, group: &Group) -> Result<Member, diesel::result::Error> {
-        log::debug!("Try to find member {user:?} of group {group:?}");
+        let _timer = metrics().db_timer("get_member");
+        log::debug!("Try to find member {} of group {}", user.id, group.id);
 
         use crate::schema::members::dsl::*;
         members
             .filter(user_id.eq(user.id))
             .filter(group_id.eq(group.id))
-            .first(&mut self.conn)
+            .first(&mut *self.conn)
     }
 
-    fn get_members(&mut self,group: &Group) -> Result<Vec<Member>, diesel::result::Error> {
-        log::debug!("Get members of group {group:?}");
+    /// Members of a group with their users, in the order they joined.
+    fn get_member_users(&mut self, group: &Group) -> Result<Vec<(Member, User)>, diesel::result::Error> {
+        let _timer = metrics().db_timer("get_member_users");
+        log::debug!("Get members of group {} with their users", group.id);
+
+        use crate::schema::{members, users};
+        members::table
+            .inner_join(users::table)
+            .filter(members::group_id.eq(group.id))
+            .order(members::id)
+            .load(&mut *self.conn)
+    }
+
+    fn get_memberships(&mut self, user: &User) -> Result<Vec<(Member, Group)>, diesel::result::Error> {
+        let _timer = metrics().db_timer("get_memberships");
+        log::debug!("Get memberships of user {}", user.id);
+
+        use crate::schema::{members, sgroups};
+        members::table
+            .inner_join(sgroups::table)
+            .filter(members::user_id.eq(user.id))
+            .load(&mut *self.conn)
+    }
+
+    fn count_members_by_group(&mut self, group_ids: &[i32]) -> Result<Vec<(i32, i64)>, diesel::result::Error> {
+        let _timer = metrics().db_timer("count_members_by_group");
+        log::debug!("Count members of {} groups", group_ids.len());
 
         use crate::schema::members::dsl::*;
         members
-            .filter(group_id.eq(group.id))
-            .load(&mut self.conn)
+            .filter(group_id.eq_any(group_ids))
+            .group_by(group_id)
+            .select((group_id, diesel::dsl::count_star()))
+            .load(&mut *self.conn)
+    }
+
+    /// Groups in which the user has been assigned a recipient.
+    fn get_santa_group_ids(&mut self, user: &User) -> Result<Vec<i32>, diesel::result::Error> {
+        let _timer = metrics().db_timer("get_santa_group_ids");
+        log::debug!("Get groups where user {} is a santa", user.id);
+
+        use crate::schema::santas::dsl::*;
+        santas
+            .filter(santa_id.eq(user.id))
+            .select(group_id)
+            .load(&mut *self.conn)
     }
 
     fn update_member(&mut self, member: Member) -> Result<usize, diesel::result::Error> {
-        log::debug!("Update member with id {} to {:?}", member.id, member);
+        let _timer = metrics().db_timer("update_member");
+        log::debug!("Update member with id {} to role {:?}", member.id, member.urole);
 
         use crate::schema::members::dsl::*;
         diesel::update(members.filter(id.eq(member.id)))
             .set(member)
-            .execute(&mut self.conn)
+            .execute(&mut *self.conn)
+    }
+
+    fn is_member(&mut self, username: &str, group: i32) -> Result<bool, diesel::result::Error> {
+        let _timer = metrics().db_timer("is_member");
+        log::debug!("Check if user {username} is a member of group {group}");
+
+        use crate::schema::{members, users};
+        diesel::select(diesel::dsl::exists(
+            members::table
+                .inner_join(users::table)
+                .filter(users::name.eq(username))
+                .filter(members::group_id.eq(group)),
+        ))
+        .get_result(&mut *self.conn)
     }
 
     fn count_admins(&mut self, group: &Group) -> Result<i64, diesel::result::Error> {
-        log::debug!("Count admins in group {group:?}");
+        let _timer = metrics().db_timer("count_admins");
+        log::debug!("Count admins in group {}", group.id);
 
         use crate::schema::members::dsl::*;
         members
             .filter(group_id.eq(group.id))
             .filter(urole.eq(Role::Admin))
             .count()
-            .get_result(&mut self.conn)
+            .get_result(&mut *self.conn)
     }
 
-    fn get_user_from_member(&mut self, member: &Member) -> Result<User, diesel::result::Error> {
-        log::debug!("Try to find user from member {member:?}");
+    fn count_group_members(&mut self, group: &Group) -> Result<i64, diesel::result::Error> {
+        let _timer = metrics().db_timer("count_group_members");
+        log::debug!("Count members in group {}", group.id);
 
-        use crate::schema::users::dsl::*;
-        users
-            .filter(id.eq(member.user_id))
-            .first(&mut self.conn)
+        use crate::schema::members::dsl::*;
+        members
+            .filter(group_id.eq(group.id))
+            .count()
+            .get_result(&mut *self.conn)
     }
 
-    fn set_santa(
+    fn count_members(&mut self) -> Result<i64, diesel::result::Error> {
+        let _timer = metrics().db_timer("count_members");
+        log::debug!("Count all members");
+
+        use crate::schema::members::dsl::*;
+        members.count().get_result(&mut *self.conn)
+    }
+
+    fn create_exclusion(
         &mut self,
         group: &Group,
-        santa: &User,
-        recipient: &User,
+        user: i32,
+        excluded_user: i32,
     ) -> Result<usize, diesel::result::Error> {
-        log::debug!("Add santa {santa:?} in group {group:?} to {recipient:?}");
+        let _timer = metrics().db_timer("create_exclusion");
+        log::debug!("Exclude {excluded_user} for {user} in group {}", group.id);
 
-        let new_santa = NewSanta {
+        let new_exclusion = NewExclusion {
             group_id: group.id,
-            santa_id: santa.id,
-            recipient_id: recipient.id,
+            user_id: user,
+            excluded_user_id: excluded_user,
         };
-        log::debug!("New santa created");
+        use crate::schema::exclusions::dsl::*;
+        diesel::insert_into(exclusions)
+            .values(new_exclusion)
+            .on_conflict_do_nothing()
+            .execute(&mut *self.conn)
+    }
+
+    fn get_exclusions(&mut self, group: &Group) -> Result<Vec<Exclusion>, diesel::result::Error> {
+        let _timer = metrics().db_timer("get_exclusions");
+        log::debug!("Get exclusions of group {}", group.id);
+
+        use crate::schema::exclusions::dsl::*;
+        exclusions
+            .filter(group_id.eq(group.id))
+            .load(&mut *self.conn)
+    }
+
+    fn set_santas(
+        &mut self,
+        group: &Group,
+        assignments: &[(&User, &User)],
+    ) -> Result<usize, diesel::result::Error> {
+        let _timer = metrics().db_timer("set_santas");
+        log::debug!("Add {} santas in group {}", assignments.len(), group.id);
+
+        let new_santas: Vec<NewSanta> = assignments.iter()
+            .map(|(santa, recipient)| NewSanta {
+                group_id: group.id,
+                santa_id: santa.id,
+                recipient_id: recipient.id,
+            })
+            .collect();
         use crate::schema::santas::dsl::*;
-        diesel::insert_into(santas).values(new_santa).execute(&mut self.conn)
+        diesel::insert_into(santas).values(new_santas).execute(&mut *self.conn)
+    }
+
+    fn create_webhook(
+        &mut self,
+        group: Option<&Group>,
+        hook_url: &str,
+        hook_secret: &str,
+    ) -> Result<Webhook, diesel::result::Error> {
+        let _timer = metrics().db_timer("create_webhook");
+        log::debug!("Create webhook for group {:?}", group.map(|group| group.id));
+
+        let new_webhook = NewWebhook {
+            group_id: group.map(|group| group.id),
+            url: hook_url,
+            secret: Some(hook_secret),
+        };
+        use crate::schema::webhooks::dsl::*;
+        diesel::insert_into(webhooks)
+            .values(new_webhook)
+            .get_result(&mut *self.conn)
+    }
+
+    fn get_webhook(&mut self, webhook_id: i32) -> Result<Webhook, diesel::result::Error> {
+        let _timer = metrics().db_timer("get_webhook");
+        log::debug!("Try to find webhook {webhook_id}");
+
+        use crate::schema::webhooks::dsl::*;
+        webhooks.filter(id.eq(webhook_id)).first(&mut *self.conn)
+    }
+
+    fn delete_webhook(&mut self, webhook: Webhook) -> Result<usize, diesel::result::Error> {
+        let _timer = metrics().db_timer("delete_webhook");
+        log::debug!("Delete webhook with id {}", webhook.id);
+
+        use crate::schema::webhooks::dsl::*;
+        diesel::delete(webhooks.filter(id.eq(webhook.id)))
+            .execute(&mut *self.conn)
+    }
+
+    /// Webhooks of the group itself.
+    fn get_group_webhooks(&mut self, group: &Group) -> Result<Vec<Webhook>, diesel::result::Error> {
+        let _timer = metrics().db_timer("get_group_webhooks");
+        log::debug!("Get webhooks of group {}", group.id);
+
+        use crate::schema::webhooks::dsl::*;
+        webhooks
+            .filter(group_id.eq(group.id))
+            .order(id)
+            .load(&mut *self.conn)
+    }
+
+    /// Webhooks that receive the events of a group, including those for every group.
+    fn get_webhooks_for_group(&mut self, group: &Group) -> Result<Vec<Webhook>, diesel::result::Error> {
+        let _timer = metrics().db_timer("get_webhooks_for_group");
+        log::debug!("Get webhooks receiving events of group {}", group.id);
+
+        use crate::schema::webhooks::dsl::*;
+        webhooks
+            .filter(group_id.eq(group.id).or(group_id.is_null()))
+            .load(&mut *self.conn)
+    }
+
+    fn get_all_webhooks(&mut self) -> Result<Vec<(Webhook, Option<Group>)>, diesel::result::Error> {
+        let _timer = metrics().db_timer("get_all_webhooks");
+        log::debug!("Get all webhooks");
+
+        use crate::schema::{sgroups, webhooks};
+        webhooks::table
+            .left_join(sgroups::table)
+            .order(webhooks::id)
+            .load(&mut *self.conn)
+    }
+
+    /// Records a delivery attempt. The webhook may have been deleted since the
+    /// event was published, taking its delivery log with it, e.g. with its group
+    /// for `group_deleted`: then nothing is recorded and 0 is returned.
+    fn log_delivery(&mut self, attempt: &DeliveryAttempt) -> Result<usize, diesel::result::Error> {
+        let _timer = metrics().db_timer("log_delivery");
+        log::debug!("Log attempt {} to deliver {} to webhook {}", attempt.attempt, attempt.event, attempt.webhook_id);
+
+        let new_delivery = NewWebhookDelivery {
+            webhook_id: attempt.webhook_id,
+            event: attempt.event,
+            attempt: attempt.attempt,
+            status_code: attempt.status_code,
+            error: attempt.error.as_deref(),
+            success: attempt.success,
+        };
+        use crate::schema::webhook_deliveries;
+        let result = diesel::insert_into(webhook_deliveries::table)
+            .values(new_delivery)
+            .execute(&mut *self.conn);
+        match result {
+            Err(DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _)) => {
+                log::debug!("Webhook {} was deleted, not recording the attempt", attempt.webhook_id);
+                Ok(0)
+            }
+            result => result,
+        }
+    }
+
+    fn log_audit_entry(&mut self, entry: &NewAuditEntry) -> Result<usize, diesel::result::Error> {
+        let _timer = metrics().db_timer("log_audit_entry");
+        log::debug!("Log {} of {:?} in group {:?}: {}", entry.action, entry.actor, entry.group_name, entry.outcome);
+
+        use crate::schema::audit_log;
+        diesel::insert_into(audit_log::table)
+            .values(entry)
+            .execute(&mut *self.conn)
+    }
+
+    fn get_audit_log(
+        &mut self,
+        group: &Group,
+        before: Option<i32>,
+        limit: i64,
+    ) -> Result<Vec<AuditEntry>, diesel::result::Error> {
+        let _timer = metrics().db_timer("get_audit_log");
+        log::debug!("Get audit log of group {} before {before:?}", group.id);
+
+        use crate::schema::audit_log;
+        let mut query = audit_log::table
+            .filter(audit_log::group_id.eq(group.id))
+            .select((
+                audit_log::id,
+                audit_log::actor,
+                audit_log::action,
+                audit_log::target,
+                audit_log::outcome,
+                audit_log::created_at,
+            ))
+            .order(audit_log::id.desc())
+            .limit(limit)
+            .into_boxed();
+        if let Some(before) = before {
+            query = query.filter(audit_log::id.lt(before));
+        }
+        query.load(&mut *self.conn)
+    }
+
+    /// Deliveries to the group's own webhooks with their URL, newest first.
+    fn get_webhook_deliveries(
+        &mut self,
+        group: &Group,
+        before: Option<i32>,
+        limit: i64,
+    ) -> Result<Vec<(WebhookDelivery, String)>, diesel::result::Error> {
+        let _timer = metrics().db_timer("get_webhook_deliveries");
+        log::debug!("Get webhook deliveries of group {} before {before:?}", group.id);
+
+        use crate::schema::{webhook_deliveries, webhooks};
+        let mut query = webhook_deliveries::table
+            .inner_join(webhooks::table)
+            .filter(webhooks::group_id.eq(group.id))
+            .select((webhook_deliveries::all_columns, webhooks::url))
+            .order(webhook_deliveries::id.desc())
+            .limit(limit)
+            .into_boxed();
+        if let Some(before) = before {
+            query = query.filter(webhook_deliveries::id.lt(before));
+        }
+        query.load(&mut *self.conn)
+    }
+
+    /// Santa and recipient names of every assignment in a group, ordered by santa.
+    fn get_assignments(&mut self, group: &Group) -> Result<Vec<(String, String)>, diesel::result::Error> {
+        let _timer = metrics().db_timer("get_assignments");
+        log::debug!("Get assignments of group {}", group.id);
+
+        use crate::schema::{santas, users};
+        let (santa_users, recipient_users) = diesel::alias!(users as santa_users, users as recipient_users);
+
+        santas::table
+            .inner_join(santa_users.on(santa_users.field(users::id).eq(santas::santa_id)))
+            .inner_join(recipient_users.on(recipient_users.field(users::id).eq(santas::recipient_id)))
+            .filter(santas::group_id.eq(group.id))
+            .select((santa_users.field(users::name), recipient_users.field(users::name)))
+            .order(santa_users.field(users::name))
+            .load(&mut *self.conn)
     }
 
     fn get_santa_recipient(&mut self, group: &Group, santa: &User) -> Result<User, diesel::result::Error> {
-        log::debug!("Get recipient for santa {santa:?} in group {group:?}");
+        let _timer = metrics().db_timer("get_santa_recipient");
+        log::debug!("Get recipient for santa {} in group {}", santa.id, group.id);
 
         use crate::schema::santas;
         use crate::schema::users;
@@ -449,10 +2247,89 @@ impl DB {
             .filter(santas::dsl::group_id.eq(group.id))
             .filter(santas::dsl::santa_id.eq(santa.id))
             .select(santas::dsl::recipient_id)
-            .first(&mut self.conn)?;
+            .first(&mut *self.conn)?;
 
         users::dsl::users
             .filter(users::dsl::id.eq(recitient_id_select))
-            .first(&mut self.conn)
+            .first(&mut *self.conn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    const BENCH_MEMBERS: usize = 1000;
+    const BENCH_RUNS: usize = 5;
+
+    /// Fastest of a few runs, in milliseconds.
+    fn best_of<T>(mut f: impl FnMut() -> T) -> (T, f64) {
+        let mut best = f64::MAX;
+        let mut result = None;
+        for _ in 0..BENCH_RUNS {
+            let start = Instant::now();
+            result = Some(f());
+            best = best.min(start.elapsed().as_secs_f64() * 1000.0);
+        }
+        (result.unwrap(), best)
+    }
+
+    /// Loads a group of 1,000 members one query per member, as the member listing
+    /// and the draw used to, and with the joined query, then times the draw.
+    /// Needs a scratch database; everything runs in a transaction that is rolled back:
+    ///
+    /// `SANTA_BENCH_DATABASE_URL=postgres://... cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_members_with_users() {
+        use crate::schema::{members, users};
+
+        let url = std::env::var("SANTA_BENCH_DATABASE_URL").expect("SANTA_BENCH_DATABASE_URL must be set");
+        let pool = Pool::builder().max_size(1).build(ConnectionManager::<PgConnection>::new(url)).unwrap();
+        let mut db = DB { conn: pool.get().unwrap() };
+        db.conn.begin_test_transaction().unwrap();
+        db.conn.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let names: Vec<String> = (0..BENCH_MEMBERS).map(|i| format!("bench-user-{i}")).collect();
+        let new_users: Vec<NewUser> = names.iter().map(|name| NewUser { name, email: None }).collect();
+        let user_ids: Vec<i32> = diesel::insert_into(users::table)
+            .values(&new_users)
+            .returning(users::id)
+            .get_results(&mut db.conn)
+            .unwrap();
+        db.create_group("bench-group", &GroupSettings::default()).unwrap();
+        let group = db.get_group("bench-group").unwrap();
+        let new_members: Vec<NewMember> = user_ids.iter()
+            .map(|&user_id| NewMember { user_id, group_id: group.id, urole: Role::Member, wishlist: None })
+            .collect();
+        diesel::insert_into(members::table).values(&new_members).execute(&mut db.conn).unwrap();
+
+        let (per_member, per_member_ms) = best_of(|| {
+            let group_members: Vec<Member> = members::table
+                .filter(members::group_id.eq(group.id))
+                .order(members::id)
+                .load(&mut db.conn)
+                .unwrap();
+            group_members.iter()
+                .map(|member| users::table.find(member.user_id).first::<User>(&mut db.conn).unwrap().name)
+                .collect::<Vec<_>>()
+        });
+        let (joined, joined_ms) = best_of(|| {
+            db.get_member_users(&group).unwrap()
+                .into_iter()
+                .map(|(_, user)| user.name)
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(per_member, joined);
+
+        let database = Database::from_pool(pool.clone(), 1);
+        let start = Instant::now();
+        let audit = Audit::new(AuditAction::CloseGroup, None, None);
+        let _recorded = database.draw(&mut db, &audit, "bench-group").unwrap();
+        let draw_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+        println!("{BENCH_MEMBERS} members: one query per member {per_member_ms:.1} ms, joined query {joined_ms:.1} ms");
+        println!("{BENCH_MEMBERS} members: draw {draw_ms:.1} ms");
     }
 }
@@ -0,0 +1,470 @@
+//! Versioned JSON export of the whole dataset, used for backups and for
+//! moving data between databases.
+//!
+//! Ids in a dump are only meaningful inside that dump: import assigns fresh ids
+//! and rewrites every reference accordingly.
+
+use crate::models::{MemberVisibility, Role};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+
+/// Version 2 added exclusions, wishlists, revealed groups, emails, webhooks,
+/// timestamps, member visibility and the capacity, draw date and budget of
+/// groups. Version 1 dumps still import, with the defaults for everything they
+/// lack.
+pub const DUMP_VERSION: u32 = 2;
+const OLDEST_DUMP_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Dump {
+    pub version: u32,
+    pub users: Vec<DumpUser>,
+    pub groups: Vec<DumpGroup>,
+    pub members: Vec<DumpMember>,
+    pub santas: Vec<DumpSanta>,
+    #[serde(default)]
+    pub exclusions: Vec<DumpExclusion>,
+    #[serde(default)]
+    pub webhooks: Vec<DumpWebhook>,
+}
+
+/// Creation and last update of a row; dumps written before timestamps existed
+/// lack them, and rows imported from such dumps get the time of the import.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+pub struct DumpTimestamps {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl DumpTimestamps {
+    pub fn new(created_at: DateTime<Utc>, updated_at: DateTime<Utc>) -> Self {
+        Self { created_at: Some(created_at), updated_at: Some(updated_at) }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DumpUser {
+    pub id: i32,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(flatten)]
+    pub timestamps: DumpTimestamps,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DumpGroup {
+    pub id: i32,
+    pub name: String,
+    pub is_closed: bool,
+    #[serde(default)]
+    pub is_revealed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capacity: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub draw_date: Option<NaiveDate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget: Option<i32>,
+    #[serde(default)]
+    pub member_visibility: DumpVisibility,
+    #[serde(flatten)]
+    pub timestamps: DumpTimestamps,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DumpRole {
+    Member,
+    Admin,
+}
+
+impl From<Role> for DumpRole {
+    fn from(role: Role) -> Self {
+        match role {
+            Role::Member => Self::Member,
+            Role::Admin => Self::Admin,
+        }
+    }
+}
+
+impl From<DumpRole> for Role {
+    fn from(role: DumpRole) -> Self {
+        match role {
+            DumpRole::Member => Self::Member,
+            DumpRole::Admin => Self::Admin,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DumpVisibility {
+    #[default]
+    Admins,
+    Members,
+    Public,
+}
+
+impl From<MemberVisibility> for DumpVisibility {
+    fn from(visibility: MemberVisibility) -> Self {
+        match visibility {
+            MemberVisibility::Admins => Self::Admins,
+            MemberVisibility::Members => Self::Members,
+            MemberVisibility::Public => Self::Public,
+        }
+    }
+}
+
+impl From<DumpVisibility> for MemberVisibility {
+    fn from(visibility: DumpVisibility) -> Self {
+        match visibility {
+            DumpVisibility::Admins => Self::Admins,
+            DumpVisibility::Members => Self::Members,
+            DumpVisibility::Public => Self::Public,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DumpMember {
+    pub user_id: i32,
+    pub group_id: i32,
+    pub role: DumpRole,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wishlist: Option<String>,
+    #[serde(flatten)]
+    pub timestamps: DumpTimestamps,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DumpSanta {
+    pub group_id: i32,
+    pub santa_id: i32,
+    pub recipient_id: i32,
+    #[serde(flatten)]
+    pub timestamps: DumpTimestamps,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DumpExclusion {
+    pub group_id: i32,
+    pub user_id: i32,
+    pub excluded_user_id: i32,
+}
+
+/// A webhook; the delivery log is not exported. The secret is only exported on
+/// request, and webhooks imported without one need a new one.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DumpWebhook {
+    pub group_id: Option<i32>,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct ImportSummary {
+    pub users: usize,
+    pub groups: usize,
+    pub members: usize,
+    pub santas: usize,
+    pub exclusions: usize,
+    pub webhooks: usize,
+}
+
+#[derive(Debug)]
+pub enum DumpError {
+    UnsupportedVersion(u32),
+    Integrity(String),
+    NotEmpty,
+    Connection(diesel::r2d2::PoolError),
+    Database(diesel::result::Error),
+}
+
+impl fmt::Display for DumpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "dump version {version} is not supported, expected {OLDEST_DUMP_VERSION} to {DUMP_VERSION}"
+            ),
+            Self::Integrity(msg) => write!(f, "dump is inconsistent: {msg}"),
+            Self::NotEmpty => write!(f, "database is not empty, use --force to replace its contents"),
+            Self::Connection(e) => write!(f, "cannot connect to the database: {e}"),
+            Self::Database(e) => write!(f, "database error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for DumpError {}
+
+impl From<diesel::result::Error> for DumpError {
+    fn from(e: diesel::result::Error) -> Self {
+        Self::Database(e)
+    }
+}
+
+fn integrity(msg: String) -> DumpError {
+    DumpError::Integrity(msg)
+}
+
+impl Dump {
+    /// Checks the version and that every reference points at a row of the dump,
+    /// so that an import can't fail halfway on a foreign key or unique constraint.
+    pub fn validate(&self) -> Result<(), DumpError> {
+        if !(OLDEST_DUMP_VERSION..=DUMP_VERSION).contains(&self.version) {
+            return Err(DumpError::UnsupportedVersion(self.version));
+        }
+
+        let mut user_ids = HashSet::new();
+        let mut user_names = HashSet::new();
+        for user in &self.users {
+            if !user_ids.insert(user.id) {
+                return Err(integrity(format!("duplicate user id {}", user.id)));
+            }
+            if !user_names.insert(user.name.as_str()) {
+                return Err(integrity(format!("duplicate user name {}", user.name)));
+            }
+        }
+
+        let mut group_ids = HashSet::new();
+        let mut group_names = HashSet::new();
+        let mut closed_groups = HashSet::new();
+        for group in &self.groups {
+            if !group_ids.insert(group.id) {
+                return Err(integrity(format!("duplicate group id {}", group.id)));
+            }
+            if !group_names.insert(group.name.as_str()) {
+                return Err(integrity(format!("duplicate group name {}", group.name)));
+            }
+            if group.capacity.is_some_and(|capacity| capacity < 3) {
+                return Err(integrity(format!("group {} has a capacity below 3", group.id)));
+            }
+            if group.budget.is_some_and(|budget| budget < 0) {
+                return Err(integrity(format!("group {} has a negative budget", group.id)));
+            }
+            if group.is_closed {
+                closed_groups.insert(group.id);
+            } else if group.is_revealed {
+                return Err(integrity(format!("group {} is revealed but not closed", group.id)));
+            }
+        }
+
+        let mut memberships = HashSet::new();
+        for member in &self.members {
+            if !user_ids.contains(&member.user_id) {
+                return Err(integrity(format!("member refers to unknown user {}", member.user_id)));
+            }
+            if !group_ids.contains(&member.group_id) {
+                return Err(integrity(format!("member refers to unknown group {}", member.group_id)));
+            }
+            if !memberships.insert((member.user_id, member.group_id)) {
+                return Err(integrity(format!(
+                    "user {} is a member of group {} twice",
+                    member.user_id, member.group_id
+                )));
+            }
+        }
+
+        let mut santas = HashSet::new();
+        for santa in &self.santas {
+            if !closed_groups.contains(&santa.group_id) {
+                return Err(integrity(format!(
+                    "santa assignment in group {} which is unknown or not closed",
+                    santa.group_id
+                )));
+            }
+            for user_id in [santa.santa_id, santa.recipient_id] {
+                if !memberships.contains(&(user_id, santa.group_id)) {
+                    return Err(integrity(format!(
+                        "santa assignment refers to user {user_id} who is not a member of group {}",
+                        santa.group_id
+                    )));
+                }
+            }
+            if !santas.insert((santa.group_id, santa.santa_id)) {
+                return Err(integrity(format!(
+                    "user {} has two recipients in group {}",
+                    santa.santa_id, santa.group_id
+                )));
+            }
+        }
+
+        let mut exclusions = HashSet::new();
+        for exclusion in &self.exclusions {
+            for user_id in [exclusion.user_id, exclusion.excluded_user_id] {
+                if !memberships.contains(&(user_id, exclusion.group_id)) {
+                    return Err(integrity(format!(
+                        "exclusion refers to user {user_id} who is not a member of group {}",
+                        exclusion.group_id
+                    )));
+                }
+            }
+            if !exclusions.insert((exclusion.group_id, exclusion.user_id, exclusion.excluded_user_id)) {
+                return Err(integrity(format!(
+                    "user {} excludes user {} twice in group {}",
+                    exclusion.user_id, exclusion.excluded_user_id, exclusion.group_id
+                )));
+            }
+        }
+
+        for webhook in &self.webhooks {
+            if let Some(group_id) = webhook.group_id {
+                if !group_ids.contains(&group_id) {
+                    return Err(integrity(format!("webhook refers to unknown group {group_id}")));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: i32, name: &str) -> DumpUser {
+        DumpUser { id, name: name.to_string(), email: None, timestamps: DumpTimestamps::default() }
+    }
+
+    fn group(id: i32, name: &str) -> DumpGroup {
+        DumpGroup {
+            id,
+            name: name.to_string(),
+            is_closed: true,
+            is_revealed: false,
+            capacity: None,
+            draw_date: None,
+            budget: None,
+            member_visibility: DumpVisibility::default(),
+            timestamps: DumpTimestamps::default(),
+        }
+    }
+
+    fn member(user_id: i32, group_id: i32) -> DumpMember {
+        DumpMember { user_id, group_id, role: DumpRole::Member, wishlist: None, timestamps: DumpTimestamps::default() }
+    }
+
+    fn santa(santa_id: i32, recipient_id: i32) -> DumpSanta {
+        DumpSanta { group_id: 1, santa_id, recipient_id, timestamps: DumpTimestamps::default() }
+    }
+
+    /// Alice, Bob and Carol in the closed group "family", where they were
+    /// drawn in a circle, and Dave in no group.
+    fn dump() -> Dump {
+        Dump {
+            version: DUMP_VERSION,
+            users: vec![user(1, "alice"), user(2, "bob"), user(3, "carol"), user(4, "dave")],
+            groups: vec![group(1, "family")],
+            members: vec![member(1, 1), member(2, 1), member(3, 1)],
+            santas: vec![santa(1, 2), santa(2, 3), santa(3, 1)],
+            exclusions: Vec::new(),
+            webhooks: Vec::new(),
+        }
+    }
+
+    fn inconsistency(dump: Dump) -> String {
+        match dump.validate() {
+            Err(DumpError::Integrity(message)) => message,
+            other => panic!("expected an inconsistent dump, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn accepts_a_consistent_dump() {
+        dump().validate().unwrap();
+    }
+
+    #[test]
+    fn rejects_member_of_unknown_user() {
+        let mut dump = dump();
+        dump.members.push(member(5, 1));
+        assert_eq!(inconsistency(dump), "member refers to unknown user 5");
+    }
+
+    #[test]
+    fn rejects_santa_or_recipient_outside_the_group() {
+        let mut dump = dump();
+        dump.santas[2] = santa(3, 4);
+        assert_eq!(inconsistency(dump), "santa assignment refers to user 4 who is not a member of group 1");
+
+        let mut dump = self::dump();
+        dump.santas[2] = santa(4, 1);
+        assert_eq!(inconsistency(dump), "santa assignment refers to user 4 who is not a member of group 1");
+    }
+
+    #[test]
+    fn rejects_duplicate_names() {
+        let mut dump = dump();
+        dump.users.push(user(5, "alice"));
+        assert_eq!(inconsistency(dump), "duplicate user name alice");
+
+        let mut dump = self::dump();
+        dump.groups.push(group(2, "family"));
+        assert_eq!(inconsistency(dump), "duplicate group name family");
+    }
+
+    #[test]
+    fn checks_group_settings() {
+        let mut dump = dump();
+        dump.groups[0].capacity = Some(2);
+        assert_eq!(inconsistency(dump), "group 1 has a capacity below 3");
+
+        let mut dump = self::dump();
+        dump.groups[0].budget = Some(-1);
+        assert_eq!(inconsistency(dump), "group 1 has a negative budget");
+
+        let mut dump = self::dump();
+        dump.groups[0].capacity = Some(3);
+        dump.groups[0].draw_date = NaiveDate::from_ymd_opt(2026, 12, 20);
+        dump.groups[0].budget = Some(25);
+        let value = serde_json::to_value(&dump).unwrap();
+        assert_eq!(value["groups"][0]["draw_date"], "2026-12-20");
+        let dump: Dump = serde_json::from_value(value).unwrap();
+        dump.validate().unwrap();
+        assert_eq!(dump.groups[0].budget, Some(25));
+    }
+
+    #[test]
+    fn rejects_unsupported_versions() {
+        for version in [0, DUMP_VERSION + 1] {
+            let dump = Dump { version, ..dump() };
+            assert!(matches!(dump.validate(), Err(DumpError::UnsupportedVersion(v)) if v == version));
+        }
+    }
+
+    #[test]
+    fn reads_version_1_dumps() {
+        let dump: Dump = serde_json::from_str(
+            r#"{"version": 1, "users": [{"id": 1, "name": "alice"}],
+                "groups": [{"id": 1, "name": "family", "is_closed": false}],
+                "members": [{"user_id": 1, "group_id": 1, "role": "admin"}], "santas": []}"#,
+        )
+        .unwrap();
+        dump.validate().unwrap();
+        assert_eq!(dump.groups[0].member_visibility, DumpVisibility::Admins);
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let mut value = serde_json::to_value(dump()).unwrap();
+        value["users"][0]["nickname"] = "al".into();
+        assert!(serde_json::from_value::<Dump>(value).is_err());
+
+        let mut value = serde_json::to_value(dump()).unwrap();
+        value["comment"] = "hand edited".into();
+        assert!(serde_json::from_value::<Dump>(value).is_err());
+    }
+}
@@ -1,22 +1,134 @@
-pub fn error_same_name(msg: String) -> tide::Error {
-    tide::Error::from_str(
-        tide::StatusCode::Conflict,
-        format!("{msg} with the same name already exists"),
-    )
-}
-
-pub fn error_internal_server() -> tide::Error {
-    tide::Error::from_str(tide::StatusCode::InternalServerError, "Internal error")
-}
-
-pub fn error_bad_request(message: String) -> tide::Error {
-    tide::Error::from_str(tide::StatusCode::BadRequest, message)
-}
-
-pub fn error_method_not_allowed(message: String) -> tide::Error {
-    tide::Error::from_str(tide::StatusCode::MethodNotAllowed, message)
-}
-
-pub fn error_too_early(message: String) -> tide::Error {
-    tide::Error::from_str(tide::StatusCode::TooEarly, message)
-}
+use serde_json::{json, Value};
+use std::fmt;
+use tide::StatusCode;
+
+#[derive(Debug)]
+pub enum ServiceError {
+    BadRequest(String),
+    UserNotFound(String),
+    GroupNotFound(String),
+    UserExists(String),
+    GroupExists(String),
+    AlreadyMember { username: String, group_name: String },
+    NotAMember { username: String, group_name: String },
+    NotAnAdmin { username: String, group_name: String },
+    GroupClosed(String),
+    GroupFull { group_name: String, capacity: i32 },
+    LastAdmin { username: String, group_name: String },
+    NotEnoughMembers { group_name: String, members: usize },
+    DrawNotStarted(String),
+    DrawImpossible(String),
+    NotRevealed(String),
+    WebhookNotFound(i32),
+    Internal,
+}
+
+impl ServiceError {
+    /// Stable machine-readable code sent to clients in the `code` field.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::BadRequest(_) => "BAD_REQUEST",
+            Self::UserNotFound(_) => "USER_NOT_FOUND",
+            Self::GroupNotFound(_) => "GROUP_NOT_FOUND",
+            Self::UserExists(_) => "USER_EXISTS",
+            Self::GroupExists(_) => "GROUP_EXISTS",
+            Self::AlreadyMember { .. } => "ALREADY_MEMBER",
+            Self::NotAMember { .. } => "NOT_A_MEMBER",
+            Self::NotAnAdmin { .. } => "NOT_AN_ADMIN",
+            Self::GroupClosed(_) => "GROUP_CLOSED",
+            Self::GroupFull { .. } => "GROUP_FULL",
+            Self::LastAdmin { .. } => "LAST_ADMIN",
+            Self::NotEnoughMembers { .. } => "NOT_ENOUGH_MEMBERS",
+            Self::DrawNotStarted(_) => "DRAW_NOT_STARTED",
+            Self::DrawImpossible(_) => "DRAW_IMPOSSIBLE",
+            Self::NotRevealed(_) => "NOT_REVEALED",
+            Self::WebhookNotFound(_) => "WEBHOOK_NOT_FOUND",
+            Self::Internal => "INTERNAL_ERROR",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::BadRequest(_) => StatusCode::BadRequest,
+            Self::UserNotFound(_) | Self::GroupNotFound(_) | Self::WebhookNotFound(_) => {
+                StatusCode::NotFound
+            }
+            Self::UserExists(_) | Self::GroupExists(_) | Self::AlreadyMember { .. } => {
+                StatusCode::Conflict
+            }
+            Self::NotAMember { .. } | Self::NotAnAdmin { .. } => StatusCode::Forbidden,
+            Self::GroupClosed(_)
+            | Self::GroupFull { .. }
+            | Self::LastAdmin { .. }
+            | Self::NotEnoughMembers { .. }
+            | Self::DrawImpossible(_)
+            | Self::NotRevealed(_) => StatusCode::Conflict,
+            Self::DrawNotStarted(_) => StatusCode::TooEarly,
+            Self::Internal => StatusCode::InternalServerError,
+        }
+    }
+
+    /// Structured context for the error, e.g. which user or group it refers to.
+    pub fn details(&self) -> Value {
+        match self {
+            Self::BadRequest(_) | Self::Internal => json!({}),
+            Self::UserNotFound(username) | Self::UserExists(username) => {
+                json!({ "username": username })
+            }
+            Self::GroupNotFound(group_name)
+            | Self::GroupExists(group_name)
+            | Self::GroupClosed(group_name)
+            | Self::DrawNotStarted(group_name)
+            | Self::DrawImpossible(group_name)
+            | Self::NotRevealed(group_name) => json!({ "group_name": group_name }),
+            Self::AlreadyMember { username, group_name }
+            | Self::NotAMember { username, group_name }
+            | Self::NotAnAdmin { username, group_name }
+            | Self::LastAdmin { username, group_name } => {
+                json!({ "username": username, "group_name": group_name })
+            }
+            Self::NotEnoughMembers { group_name, members } => {
+                json!({ "group_name": group_name, "members": members })
+            }
+            Self::GroupFull { group_name, capacity } => {
+                json!({ "group_name": group_name, "capacity": capacity })
+            }
+            Self::WebhookNotFound(webhook_id) => json!({ "webhook_id": webhook_id }),
+        }
+    }
+}
+
+impl fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadRequest(msg) => write!(f, "{msg}"),
+            Self::UserNotFound(username) => write!(f, "User {username} not found"),
+            Self::GroupNotFound(group_name) => write!(f, "Group {group_name} not found"),
+            Self::UserExists(_) => write!(f, "User with the same name already exists"),
+            Self::GroupExists(_) => write!(f, "Group with the same name already exists"),
+            Self::AlreadyMember { .. } => write!(f, "User is already a member of this group"),
+            Self::NotAMember { .. } => write!(f, "User is not a member of this group"),
+            Self::NotAnAdmin { .. } => write!(f, "User is not an admin of this group"),
+            Self::GroupClosed(_) => write!(f, "Group is closed"),
+            Self::GroupFull { .. } => write!(f, "Group is full"),
+            Self::LastAdmin { .. } => write!(f, "You are the only admin"),
+            Self::NotEnoughMembers { .. } => write!(f, "Not enough members"),
+            Self::DrawNotStarted(_) => write!(f, "It's too early to recognize recipient"),
+            Self::DrawImpossible(_) => write!(f, "No assignment satisfies the exclusions of this group"),
+            Self::NotRevealed(_) => write!(f, "Assignments of this group are still secret"),
+            Self::WebhookNotFound(webhook_id) => write!(f, "Webhook {webhook_id} not found"),
+            Self::Internal => write!(f, "Internal error"),
+        }
+    }
+}
+
+impl std::error::Error for ServiceError {}
+
+/// Database errors that aren't mapped to a more specific error where they occur,
+/// e.g. failing to begin or commit a transaction.
+impl From<diesel::result::Error> for ServiceError {
+    fn from(e: diesel::result::Error) -> Self {
+        tide::log::error!("Database error: {e}");
+        Self::Internal
+    }
+}
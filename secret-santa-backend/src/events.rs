@@ -0,0 +1,159 @@
+//! Changes to a group that are published to its webhooks and to the members
+//! following `/group-events`.
+//!
+//! Events never carry assignments: anyone subscribed to a group may see them.
+
+use async_std::channel::{self, Receiver, Sender, TrySendError};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tide::log;
+
+/// Events buffered per subscriber; a subscriber that falls further behind is dropped.
+const SUBSCRIBER_BUFFER: usize = 64;
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum GroupEvent {
+    GroupCreated,
+    MemberJoined { username: String },
+    MemberLeft { username: String },
+    AdminChanged { username: String, is_admin: bool },
+    DrawCompleted { members: usize },
+    GroupDeleted,
+}
+
+impl GroupEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::GroupCreated => "group_created",
+            Self::MemberJoined { .. } => "member_joined",
+            Self::MemberLeft { .. } => "member_left",
+            Self::AdminChanged { .. } => "admin_changed",
+            Self::DrawCompleted { .. } => "draw_completed",
+            Self::GroupDeleted => "group_deleted",
+        }
+    }
+}
+
+/// JSON body of a published event.
+#[derive(Serialize)]
+pub struct EventEnvelope<'a> {
+    pub group_name: &'a str,
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: &'a GroupEvent,
+}
+
+/// An event as sent to stream subscribers: its name and JSON body.
+pub type StreamEvent = (&'static str, String);
+
+struct Subscriber {
+    user_id: i32,
+    sender: Sender<StreamEvent>,
+}
+
+/// Live subscribers of the event streams, by group id.
+#[derive(Clone, Default)]
+pub struct EventStreams {
+    subscribers: Arc<Mutex<HashMap<i32, Vec<Subscriber>>>>,
+}
+
+impl EventStreams {
+    pub fn subscribe(&self, group_id: i32, user_id: i32) -> Receiver<StreamEvent> {
+        let (sender, receiver) = channel::bounded(SUBSCRIBER_BUFFER);
+        self.subscribers.lock().unwrap()
+            .entry(group_id)
+            .or_default()
+            .push(Subscriber { user_id, sender });
+        receiver
+    }
+
+    pub fn broadcast(&self, group_id: i32, name: &'static str, body: &str) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let Some(group_subscribers) = subscribers.get_mut(&group_id) else { return };
+        group_subscribers.retain(|subscriber| {
+            match subscriber.sender.try_send((name, body.to_string())) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    log::warn!("Dropping slow event stream of user {} in group {group_id}", subscriber.user_id);
+                    false
+                }
+                Err(TrySendError::Closed(_)) => false,
+            }
+        });
+        if group_subscribers.is_empty() {
+            subscribers.remove(&group_id);
+        }
+    }
+
+    /// Ends the streams of a user who is no longer a member of the group.
+    pub fn unsubscribe(&self, group_id: i32, user_id: i32) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if let Some(group_subscribers) = subscribers.get_mut(&group_id) {
+            group_subscribers.retain(|subscriber| subscriber.user_id != user_id);
+        }
+    }
+
+    /// Ends every stream of a deleted group.
+    pub fn close(&self, group_id: i32) {
+        self.subscribers.lock().unwrap().remove(&group_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn envelope_is_flat_and_named_like_the_event() {
+        let event = GroupEvent::MemberJoined { username: "alice".to_string() };
+        let envelope = EventEnvelope {
+            group_name: "friends",
+            timestamp: DateTime::from_timestamp(0, 0).unwrap(),
+            event: &event,
+        };
+        assert_eq!(
+            serde_json::to_value(&envelope).unwrap(),
+            serde_json::json!({
+                "group_name": "friends",
+                "timestamp": "1970-01-01T00:00:00Z",
+                "event": event.name(),
+                "username": "alice",
+            })
+        );
+    }
+
+    #[test]
+    fn streams_reach_subscribers_of_the_group_until_they_leave() {
+        let streams = EventStreams::default();
+        let alice = streams.subscribe(1, 10);
+        let bob = streams.subscribe(1, 11);
+        let carol = streams.subscribe(2, 12);
+
+        streams.broadcast(1, "member_joined", "{}");
+        streams.unsubscribe(1, 11);
+        streams.broadcast(1, "draw_completed", "{}");
+        streams.close(1);
+
+        assert_eq!(alice.try_recv(), Ok(("member_joined", "{}".to_string())));
+        assert_eq!(alice.try_recv(), Ok(("draw_completed", "{}".to_string())));
+        assert!(alice.is_closed());
+        assert_eq!(bob.try_recv(), Ok(("member_joined", "{}".to_string())));
+        assert!(bob.is_closed());
+        assert!(carol.try_recv().is_err());
+        assert!(!carol.is_closed());
+    }
+
+    #[test]
+    fn slow_subscribers_are_dropped() {
+        let streams = EventStreams::default();
+        let alice = streams.subscribe(1, 10);
+        for _ in 0..=SUBSCRIBER_BUFFER {
+            streams.broadcast(1, "member_joined", "{}");
+        }
+        assert_eq!(alice.len(), SUBSCRIBER_BUFFER);
+        assert!(alice.is_closed());
+    }
+}
@@ -0,0 +1,813 @@
+use crate::database::Database;
+use crate::errors::ServiceError;
+use crate::json_models::*;
+use crate::logging;
+use crate::openapi::ApiDoc;
+use serde::Serialize;
+use std::time::Duration;
+use tide::http::Method;
+use tide::{log, Body, Endpoint, Request, Response, StatusCode};
+use utoipa::OpenApi;
+
+pub type State = Database;
+
+/// Silence after which an event stream is pinged, which also notices closed connections.
+const EVENT_STREAM_PING_INTERVAL: Duration = Duration::from_secs(30);
+
+fn make_error_response(e: ServiceError) -> Response {
+    let mut response = Response::new(e.status());
+    log::debug!("Error {}: {e}", e.code());
+    let error = ErrorResponse {
+        code: e.code().to_string(),
+        error_message: e.to_string(),
+        details: e.details(),
+        request_id: logging::request_id(),
+    };
+    match Body::from_json(&error) {
+        Ok(body) => response.set_body(body),
+        Err(_) => response.set_status(StatusCode::InternalServerError),
+    }
+    response
+}
+
+fn make_json_response<T: Serialize>(status: StatusCode, value: &T) -> Response {
+    match Body::from_json(value) {
+        Ok(body) => {
+            let mut response = Response::new(status);
+            response.set_body(body);
+            response
+        }
+        Err(_) => make_error_response(ServiceError::Internal),
+    }
+}
+
+fn make_response_from_result<T: Serialize>(result: Result<T, ServiceError>) -> Response {
+    match result {
+        Ok(value) => make_json_response(StatusCode::Ok, &value),
+        Err(e) => make_error_response(e),
+    }
+}
+
+fn make_csv_response<T: Serialize>(rows: &[T]) -> Response {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        if writer.serialize(row).is_err() {
+            return make_error_response(ServiceError::Internal);
+        }
+    }
+    match writer.into_inner().map(String::from_utf8) {
+        Ok(Ok(body)) => {
+            let mut response = Response::new(StatusCode::Ok);
+            response.set_body(body);
+            response.set_content_type("text/csv");
+            response
+        }
+        _ => make_error_response(ServiceError::Internal),
+    }
+}
+
+fn bad_request() -> Response {
+    make_error_response(ServiceError::BadRequest("Incorect request".to_string()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/version",
+    responses((status = 200, body = VersionResponse))
+)]
+pub async fn version(_request: Request<State>) -> tide::Result {
+    Ok(make_response_from_result(Ok(VersionResponse {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    responses((status = 200, body = HealthResponse, description = "The process is alive"))
+)]
+pub async fn healthz(_request: Request<State>) -> tide::Result {
+    Ok(make_json_response(StatusCode::Ok, &HealthResponse { status: "ok".to_string() }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    responses(
+        (status = 200, body = ReadinessResponse, description = "Ready to serve traffic"),
+        (status = 503, body = ReadinessResponse, description = "Database unreachable or migrations pending"),
+    )
+)]
+pub async fn readyz(request: Request<State>) -> tide::Result {
+    let database = request.state();
+
+    let readiness = match database.run(|database| Ok(database.check_readiness())).await {
+        Ok(readiness) => readiness,
+        Err(e) => return Ok(make_error_response(e)),
+    };
+    let status = match readiness.ready {
+        true => StatusCode::Ok,
+        false => StatusCode::ServiceUnavailable,
+    };
+    Ok(make_json_response(status, &readiness))
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses((status = 200, content_type = "text/plain", description = "Metrics in Prometheus text format"))
+)]
+pub async fn metrics(request: Request<State>) -> tide::Result {
+    let database = request.state();
+
+    if let Err(e) = database.run(|database| database.refresh_metrics()).await {
+        log::warn!("Cannot refresh domain metrics: {e}");
+    }
+    let mut response = Response::new(StatusCode::Ok);
+    response.set_body(crate::metrics::metrics().render());
+    response.set_content_type("text/plain; version=0.0.4");
+    Ok(response)
+}
+
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    responses((status = 200, description = "OpenAPI 3 document of this service"))
+)]
+pub async fn openapi_json(_request: Request<State>) -> tide::Result {
+    Ok(make_response_from_result(Ok(ApiDoc::openapi())))
+}
+
+#[utoipa::path(
+    post,
+    path = "/registr-user",
+    request_body = Username,
+    responses(
+        (status = 200, body = StatusResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 409, body = ErrorResponse, description = "USER_EXISTS"),
+    )
+)]
+pub async fn registr_user(mut request: Request<State>) -> tide::Result {
+    let Username { username, email } = match request.body_json().await {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+    logging::set_user(&username);
+
+    let database = request.state();
+
+    Ok(make_response_from_result(
+        database.run(move |database| {
+            database.create_user(username.as_str(), email.as_deref())
+        }).await
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/set-email",
+    request_body = UserEmail,
+    responses(
+        (status = 200, body = StatusResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse, description = "USER_NOT_FOUND"),
+    )
+)]
+pub async fn set_email(mut request: Request<State>) -> tide::Result {
+    let UserEmail { username, email } = match request.body_json().await {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+    logging::set_user(&username);
+
+    let database = request.state();
+
+    Ok(make_response_from_result(
+        database.run(move |database| {
+            database.set_user_email(username.as_str(), email.as_deref())
+        }).await
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/create-group",
+    request_body = CreateGroupRequest,
+    responses(
+        (status = 200, body = StatusResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse, description = "USER_NOT_FOUND"),
+        (status = 409, body = ErrorResponse, description = "GROUP_EXISTS"),
+    )
+)]
+pub async fn create_group(mut request: Request<State>) -> tide::Result {
+    let CreateGroupRequest { username, group_name, settings } = match request.body_json().await {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+    logging::set_user(&username);
+
+    let database = request.state();
+
+    Ok(make_response_from_result(
+        database.run(move |database| {
+            database.create_group_by_user(username.as_str(), group_name.as_str(), &settings)
+        }).await
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/join-group",
+    request_body = UserGroupName,
+    responses(
+        (status = 200, body = StatusResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse, description = "USER_NOT_FOUND, GROUP_NOT_FOUND"),
+        (status = 409, body = ErrorResponse, description = "GROUP_CLOSED, GROUP_FULL, ALREADY_MEMBER"),
+    )
+)]
+pub async fn join_group(mut request: Request<State>) -> tide::Result {
+    let UserGroupName { username, group_name } = match request.body_json().await {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+    logging::set_user(&username);
+
+    let database = request.state();
+
+    Ok(make_response_from_result(
+        database.run(move |database| {
+            database.add_user_to_group(username.as_str(), group_name.as_str())
+        }).await
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/delete-group",
+    request_body = UserGroupName,
+    responses(
+        (status = 200, body = StatusResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 403, body = ErrorResponse, description = "NOT_A_MEMBER, NOT_AN_ADMIN"),
+        (status = 404, body = ErrorResponse, description = "USER_NOT_FOUND, GROUP_NOT_FOUND"),
+    )
+)]
+pub async fn delete_group(mut request: Request<State>) -> tide::Result {
+    let UserGroupName { username, group_name } = match request.body_json().await {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+    logging::set_user(&username);
+
+    let database = request.state();
+
+    Ok(make_response_from_result(
+        database.run(move |database| {
+            database.delete_group_by_admin(username.as_str(), group_name.as_str())
+        }).await
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/my-groups",
+    params(UserQuery),
+    responses(
+        (status = 200, body = MyGroupsResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse, description = "USER_NOT_FOUND"),
+    )
+)]
+pub async fn my_groups(request: Request<State>) -> tide::Result {
+    let UserQuery { username } = match request.query() {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+    logging::set_user(&username);
+
+    let database = request.state();
+
+    Ok(make_response_from_result(
+        database.run(move |database| {
+            database.get_user_groups(username.as_str())
+        }).await
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/group-members",
+    params(UserGroupQuery),
+    responses(
+        (status = 200, body = GroupMembersResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 403, body = ErrorResponse, description = "NOT_A_MEMBER, NOT_AN_ADMIN"),
+        (status = 404, body = ErrorResponse, description = "USER_NOT_FOUND, GROUP_NOT_FOUND"),
+    )
+)]
+pub async fn group_members(request: Request<State>) -> tide::Result {
+    let UserGroupQuery { username, group_name } = match request.query() {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+    logging::set_user(&username);
+
+    let database = request.state();
+
+    Ok(make_response_from_result(
+        database.run(move |database| {
+            database.get_group_members(username.as_str(), group_name.as_str())
+        }).await
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/group-events",
+    params(UserGroupQuery),
+    responses(
+        (
+            status = 200,
+            content_type = "text/event-stream",
+            description = "Server-sent events named member_joined, member_left, admin_changed, \
+                draw_completed and group_deleted, with the same JSON data as webhooks, \
+                and a ping event every 30 seconds of silence",
+        ),
+        (status = 400, body = ErrorResponse),
+        (status = 403, body = ErrorResponse, description = "NOT_A_MEMBER"),
+        (status = 404, body = ErrorResponse, description = "USER_NOT_FOUND, GROUP_NOT_FOUND"),
+    )
+)]
+pub async fn group_events(request: Request<State>) -> tide::Result {
+    let UserGroupQuery { username, group_name } = match request.query() {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+    logging::set_user(&username);
+
+    let subscription = request.state()
+        .run(move |database| database.subscribe_to_group_events(username.as_str(), group_name.as_str()))
+        .await;
+    let receiver = match subscription {
+        Ok(receiver) => receiver,
+        Err(e) => return Ok(make_error_response(e)),
+    };
+
+    Ok(tide::sse::upgrade(request, move |_request, sender| {
+        let receiver = receiver.clone();
+        async move {
+            loop {
+                match async_std::future::timeout(EVENT_STREAM_PING_INTERVAL, receiver.recv()).await {
+                    Ok(Ok((name, body))) => sender.send(name, body, None).await?,
+                    Ok(Err(_)) => return Ok(()),
+                    Err(_) => sender.send("ping", "", None).await?,
+                }
+            }
+        }
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/set-member-visibility",
+    request_body = MemberVisibilityRequest,
+    responses(
+        (status = 200, body = StatusResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 403, body = ErrorResponse, description = "NOT_A_MEMBER, NOT_AN_ADMIN"),
+        (status = 404, body = ErrorResponse, description = "USER_NOT_FOUND, GROUP_NOT_FOUND"),
+    )
+)]
+pub async fn set_member_visibility(mut request: Request<State>) -> tide::Result {
+    let MemberVisibilityRequest { username, group_name, visibility } = match request.body_json().await {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+    logging::set_user(&username);
+
+    let database = request.state();
+
+    Ok(make_response_from_result(
+        database.run(move |database| {
+            database.set_member_visibility(username.as_str(), group_name.as_str(), visibility.into())
+        }).await
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/import-members",
+    params(UserGroupQuery),
+    request_body(
+        content = String,
+        content_type = "text/csv",
+        description = "Header with a username column and optional exclude (separated by ;) and wishlist columns",
+    ),
+    responses(
+        (status = 200, body = ImportMembersResponse, description = "Report with one entry per CSV line"),
+        (status = 400, body = ErrorResponse),
+        (status = 403, body = ErrorResponse, description = "NOT_A_MEMBER, NOT_AN_ADMIN"),
+        (status = 404, body = ErrorResponse, description = "USER_NOT_FOUND, GROUP_NOT_FOUND"),
+        (status = 409, body = ErrorResponse, description = "GROUP_CLOSED"),
+    )
+)]
+pub async fn import_members(mut request: Request<State>) -> tide::Result {
+    let UserGroupQuery { username, group_name } = match request.query() {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+    logging::set_user(&username);
+    let csv = match request.body_string().await {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+
+    let database = request.state();
+
+    Ok(make_response_from_result(
+        database.run(move |database| {
+            database.import_members_by_admin(username.as_str(), group_name.as_str(), csv.as_str())
+        }).await
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/get-recipient-name",
+    params(UserGroupQuery),
+    responses(
+        (status = 200, body = RecipientResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 403, body = ErrorResponse, description = "NOT_A_MEMBER"),
+        (status = 404, body = ErrorResponse, description = "USER_NOT_FOUND, GROUP_NOT_FOUND"),
+        (status = 425, body = ErrorResponse, description = "DRAW_NOT_STARTED"),
+    )
+)]
+pub async fn get_recipient_name(request: Request<State>) -> tide::Result {
+    let UserGroupQuery { username, group_name } = match request.query() {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+    logging::set_user(&username);
+
+    let database = request.state();
+
+    Ok(make_response_from_result(
+        database.run(move |database| {
+            database.get_recipient_name(username.as_str(), group_name.as_str())
+        }).await
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/add-admin",
+    request_body = UserGroupNewAdminName,
+    responses(
+        (status = 200, body = StatusResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 403, body = ErrorResponse, description = "NOT_A_MEMBER, NOT_AN_ADMIN"),
+        (status = 404, body = ErrorResponse, description = "USER_NOT_FOUND, GROUP_NOT_FOUND"),
+    )
+)]
+pub async fn add_admin(mut request: Request<State>) -> tide::Result {
+    let UserGroupNewAdminName { username, group_name, new_admin } = match request.body_json().await {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+    logging::set_user(&username);
+
+    let database = request.state();
+
+    Ok(make_response_from_result(
+        database.run(move |database| {
+            database.add_admin_to_group(username.as_str(), new_admin.as_str(), group_name.as_str())
+        }).await
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/start-secret-santa",
+    request_body = UserGroupName,
+    responses(
+        (status = 200, body = StatusResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 403, body = ErrorResponse, description = "NOT_A_MEMBER, NOT_AN_ADMIN"),
+        (status = 404, body = ErrorResponse, description = "USER_NOT_FOUND, GROUP_NOT_FOUND"),
+        (status = 409, body = ErrorResponse, description = "GROUP_CLOSED, NOT_ENOUGH_MEMBERS, DRAW_IMPOSSIBLE"),
+    )
+)]
+pub async fn start_secret_santa(mut request: Request<State>) -> tide::Result {
+    let UserGroupName { username, group_name } = match request.body_json().await {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+    logging::set_user(&username);
+
+    let database = request.state();
+
+    Ok(make_response_from_result(
+        database.run(move |database| {
+            database.close_group(username.as_str(), group_name.as_str())
+        }).await
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/revoke-admin-rights",
+    request_body = UserGroupName,
+    responses(
+        (status = 200, body = StatusResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 403, body = ErrorResponse, description = "NOT_A_MEMBER, NOT_AN_ADMIN"),
+        (status = 404, body = ErrorResponse, description = "USER_NOT_FOUND, GROUP_NOT_FOUND"),
+        (status = 409, body = ErrorResponse, description = "LAST_ADMIN"),
+    )
+)]
+pub async fn revoke_admin_rights(mut request: Request<State>) -> tide::Result {
+    let UserGroupName { username, group_name } = match request.body_json().await {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+    logging::set_user(&username);
+
+    let database = request.state();
+
+    Ok(make_response_from_result(
+        database.run(move |database| {
+            database.revoke_rights_of_admin(username.as_str(), group_name.as_str())
+        }).await
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/reveal-assignments",
+    request_body = UserGroupName,
+    responses(
+        (status = 200, body = StatusResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 403, body = ErrorResponse, description = "NOT_A_MEMBER, NOT_AN_ADMIN"),
+        (status = 404, body = ErrorResponse, description = "USER_NOT_FOUND, GROUP_NOT_FOUND"),
+        (status = 425, body = ErrorResponse, description = "DRAW_NOT_STARTED"),
+    )
+)]
+pub async fn reveal_assignments(mut request: Request<State>) -> tide::Result {
+    let UserGroupName { username, group_name } = match request.body_json().await {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+    logging::set_user(&username);
+
+    let database = request.state();
+
+    Ok(make_response_from_result(
+        database.run(move |database| {
+            database.reveal_assignments(username.as_str(), group_name.as_str())
+        }).await
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/export-assignments",
+    params(AssignmentsQuery),
+    responses(
+        (status = 200, body = AssignmentsResponse, description = "Who gives to whom; with format=csv, text/csv with santa and recipient columns"),
+        (status = 400, body = ErrorResponse),
+        (status = 403, body = ErrorResponse, description = "NOT_A_MEMBER, NOT_AN_ADMIN"),
+        (status = 404, body = ErrorResponse, description = "USER_NOT_FOUND, GROUP_NOT_FOUND"),
+        (status = 409, body = ErrorResponse, description = "NOT_REVEALED"),
+        (status = 425, body = ErrorResponse, description = "DRAW_NOT_STARTED"),
+    )
+)]
+pub async fn export_assignments(request: Request<State>) -> tide::Result {
+    let AssignmentsQuery { username, group_name, format } = match request.query() {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+    logging::set_user(&username);
+
+    let database = request.state();
+
+    let result = database
+        .run(move |database| database.get_assignments(username.as_str(), group_name.as_str()))
+        .await;
+    Ok(match (format, result) {
+        (ExportFormat::Csv, Ok(assignments)) => make_csv_response(&assignments.assignments),
+        (_, result) => make_response_from_result(result),
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/add-webhook",
+    request_body = AddWebhookRequest,
+    responses(
+        (status = 200, body = WebhookResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 403, body = ErrorResponse, description = "NOT_A_MEMBER, NOT_AN_ADMIN"),
+        (status = 404, body = ErrorResponse, description = "USER_NOT_FOUND, GROUP_NOT_FOUND"),
+    )
+)]
+pub async fn add_webhook(mut request: Request<State>) -> tide::Result {
+    let AddWebhookRequest { username, group_name, url, secret } = match request.body_json().await {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+    logging::set_user(&username);
+
+    let database = request.state();
+
+    Ok(make_response_from_result(
+        database.run(move |database| {
+            database.add_webhook(username.as_str(), group_name.as_str(), url.as_str(), secret.as_str())
+        }).await
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/remove-webhook",
+    request_body = WebhookIdRequest,
+    responses(
+        (status = 200, body = StatusResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 403, body = ErrorResponse, description = "NOT_A_MEMBER, NOT_AN_ADMIN"),
+        (status = 404, body = ErrorResponse, description = "USER_NOT_FOUND, GROUP_NOT_FOUND, WEBHOOK_NOT_FOUND"),
+    )
+)]
+pub async fn remove_webhook(mut request: Request<State>) -> tide::Result {
+    let WebhookIdRequest { username, group_name, webhook_id } = match request.body_json().await {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+    logging::set_user(&username);
+
+    let database = request.state();
+
+    Ok(make_response_from_result(
+        database.run(move |database| {
+            database.remove_webhook(username.as_str(), group_name.as_str(), webhook_id)
+        }).await
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks",
+    params(UserGroupQuery),
+    responses(
+        (status = 200, body = WebhooksResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 403, body = ErrorResponse, description = "NOT_A_MEMBER, NOT_AN_ADMIN"),
+        (status = 404, body = ErrorResponse, description = "USER_NOT_FOUND, GROUP_NOT_FOUND"),
+    )
+)]
+pub async fn webhooks(request: Request<State>) -> tide::Result {
+    let UserGroupQuery { username, group_name } = match request.query() {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+    logging::set_user(&username);
+
+    let database = request.state();
+
+    Ok(make_response_from_result(
+        database.run(move |database| {
+            database.get_webhooks(username.as_str(), group_name.as_str())
+        }).await
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhook-deliveries",
+    params(GroupPageQuery),
+    responses(
+        (status = 200, body = WebhookDeliveriesResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 403, body = ErrorResponse, description = "NOT_A_MEMBER, NOT_AN_ADMIN"),
+        (status = 404, body = ErrorResponse, description = "USER_NOT_FOUND, GROUP_NOT_FOUND"),
+    )
+)]
+pub async fn webhook_deliveries(request: Request<State>) -> tide::Result {
+    let GroupPageQuery { username, group_name, before, limit } = match request.query() {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+    logging::set_user(&username);
+
+    let database = request.state();
+
+    Ok(make_response_from_result(
+        database.run(move |database| {
+            database.get_webhook_deliveries(username.as_str(), group_name.as_str(), before, limit)
+        }).await
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/audit-log",
+    params(GroupPageQuery),
+    responses(
+        (status = 200, body = AuditLogResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 403, body = ErrorResponse, description = "NOT_A_MEMBER, NOT_AN_ADMIN"),
+        (status = 404, body = ErrorResponse, description = "USER_NOT_FOUND, GROUP_NOT_FOUND"),
+    )
+)]
+pub async fn audit_log(request: Request<State>) -> tide::Result {
+    let GroupPageQuery { username, group_name, before, limit } = match request.query() {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+    logging::set_user(&username);
+
+    let database = request.state();
+
+    Ok(make_response_from_result(
+        database.run(move |database| {
+            database.get_audit_log(username.as_str(), group_name.as_str(), before, limit)
+        }).await
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/get-groups",
+    params(GroupsQuery),
+    responses(
+        (status = 200, body = GroupsResponse),
+        (status = 400, body = ErrorResponse),
+    )
+)]
+pub async fn get_groups(request: Request<State>) -> tide::Result {
+    let query: GroupsQuery = match request.query() {
+        Ok(v) => v,
+        Err(_) => return Ok(bad_request()),
+    };
+
+    let database = request.state();
+
+    Ok(make_response_from_result(
+        database.run(move |database| {
+            database.get_open_groups(&query)
+        }).await
+    ))
+}
+
+/// A route of the service: the method and path a handler answers.
+pub struct Route {
+    pub method: Method,
+    pub path: &'static str,
+    pub endpoint: Box<dyn Endpoint<State>>,
+}
+
+fn get(path: &'static str, endpoint: impl Endpoint<State>) -> Route {
+    Route { method: Method::Get, path, endpoint: Box::new(endpoint) }
+}
+
+fn post(path: &'static str, endpoint: impl Endpoint<State>) -> Route {
+    Route { method: Method::Post, path, endpoint: Box::new(endpoint) }
+}
+
+/// Every route of the service. `main.rs` registers them and the OpenAPI test
+/// checks them against the document, so a route is added here and only here.
+pub fn routes() -> Vec<Route> {
+    vec![
+        get("/version", version),
+        get("/healthz", healthz),
+        get("/readyz", readyz),
+        get("/metrics", metrics),
+        get("/openapi.json", openapi_json),
+        post("/registr-user", registr_user),
+        post("/set-email", set_email),
+        post("/create-group", create_group),
+        post("/join-group", join_group),
+        post("/delete-group", delete_group),
+        get("/my-groups", my_groups),
+        get("/group-members", group_members),
+        get("/group-events", group_events),
+        post("/set-member-visibility", set_member_visibility),
+        post("/import-members", import_members),
+        get("/get-recipient-name", get_recipient_name),
+        post("/add-admin", add_admin),
+        post("/start-secret-santa", start_secret_santa),
+        post("/revoke-admin-rights", revoke_admin_rights),
+        post("/reveal-assignments", reveal_assignments),
+        get("/export-assignments", export_assignments),
+        post("/add-webhook", add_webhook),
+        post("/remove-webhook", remove_webhook),
+        get("/webhooks", webhooks),
+        get("/webhook-deliveries", webhook_deliveries),
+        get("/audit-log", audit_log),
+        get("/get-groups", get_groups),
+    ]
+}
@@ -1,19 +1,515 @@
-use serde::Deserialize;
+use crate::errors::ServiceError;
+use crate::models::{Group, Member, MemberVisibility, Role, User};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct Username {
     pub username: String,
+    /// Address for notification emails
+    #[serde(default)]
+    pub email: Option<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
+pub struct UserEmail {
+    pub username: String,
+    /// New address for notification emails; `null` stops them
+    pub email: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
 pub struct UserGroupName {
     pub username: String,
     pub group_name: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
+pub struct CreateGroupRequest {
+    pub username: String,
+    pub group_name: String,
+    #[serde(flatten)]
+    pub settings: GroupSettings,
+}
+
+/// Optional settings of a group, fixed when it is created.
+#[derive(Deserialize, ToSchema, Default, Clone, Debug)]
+pub struct GroupSettings {
+    /// Most members the group takes, at least 3
+    #[serde(default)]
+    pub capacity: Option<i32>,
+    /// Day of the draw
+    #[serde(default)]
+    pub draw_date: Option<NaiveDate>,
+    /// Gift budget in whole currency units
+    #[serde(default)]
+    pub budget: Option<i32>,
+}
+
+impl GroupSettings {
+    pub fn check(&self) -> Result<(), ServiceError> {
+        if self.capacity.is_some_and(|capacity| capacity < 3) {
+            return Err(ServiceError::BadRequest("capacity must be at least 3".to_string()));
+        }
+        if self.budget.is_some_and(|budget| budget < 0) {
+            return Err(ServiceError::BadRequest("budget must not be negative".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
 pub struct UserGroupNewAdminName {
     pub username: String,
     pub group_name: String,
     pub new_admin: String,
 }
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UserQuery {
+    pub username: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UserGroupQuery {
+    pub username: String,
+    pub group_name: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct VersionResponse {
+    pub version: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct StatusResponse {
+    pub status: String,
+}
+
+impl StatusResponse {
+    pub fn ok() -> Self {
+        Self {
+            status: "ok".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub code: String,
+    pub error_message: String,
+    #[schema(value_type = Object)]
+    pub details: serde_json::Value,
+    pub request_id: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct GroupMembersResponse {
+    pub group_name: String,
+    pub members: Vec<MemberResponse>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RecipientResponse {
+    pub recipient_name: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct GroupResponse {
+    pub group_name: String,
+    pub is_closed: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub capacity: Option<i32>,
+    pub draw_date: Option<NaiveDate>,
+    pub budget: Option<i32>,
+}
+
+impl From<Group> for GroupResponse {
+    fn from(group: Group) -> Self {
+        Self {
+            group_name: group.gname,
+            is_closed: group.is_close,
+            created_at: group.created_at,
+            updated_at: group.updated_at,
+            capacity: group.capacity,
+            draw_date: group.draw_date,
+            budget: group.budget,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct GroupsResponse {
+    pub groups: Vec<GroupResponse>,
+    /// Pass as `cursor` to get the next page; absent on the last page
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupSort {
+    #[default]
+    Name,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Deserialize, IntoParams, Default)]
+#[into_params(parameter_in = Query)]
+pub struct GroupsQuery {
+    /// `name` (the default), `created_at` or `updated_at`
+    #[serde(default)]
+    #[param(inline)]
+    pub sort: GroupSort,
+    /// `asc` (the default) or `desc`
+    #[serde(default)]
+    #[param(inline)]
+    pub order: SortOrder,
+    /// Only groups whose name contains this, ignoring case
+    pub search: Option<String>,
+    /// The `next_cursor` of the previous page, requested with the same sort and order
+    pub cursor: Option<String>,
+    /// Page size, 50 by default and at most 200
+    pub limit: Option<i64>,
+    /// Only groups with (`true`) or without (`false`) a free spot; groups without
+    /// a capacity always have one
+    pub has_space: Option<bool>,
+    /// Only groups drawing on or after this day
+    pub draw_from: Option<NaiveDate>,
+    /// Only groups drawing on or before this day
+    pub draw_until: Option<NaiveDate>,
+    /// Only groups with a budget of at least this much
+    pub min_budget: Option<i32>,
+    /// Only groups with a budget of at most this much
+    pub max_budget: Option<i32>,
+}
+
+impl GroupsQuery {
+    pub fn check(&self) -> Result<(), ServiceError> {
+        if let (Some(from), Some(until)) = (self.draw_from, self.draw_until) {
+            if from > until {
+                return Err(ServiceError::BadRequest("draw_from is after draw_until".to_string()));
+            }
+        }
+        if let (Some(min), Some(max)) = (self.min_budget, self.max_budget) {
+            if min > max {
+                return Err(ServiceError::BadRequest("min_budget is above max_budget".to_string()));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, ToSchema, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum RoleResponse {
+    Member,
+    Admin,
+}
+
+impl From<Role> for RoleResponse {
+    fn from(role: Role) -> Self {
+        match role {
+            Role::Member => Self::Member,
+            Role::Admin => Self::Admin,
+        }
+    }
+}
+
+/// Who may list a group's members: its admins, its members, or every registered user.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    Admins,
+    Members,
+    Public,
+}
+
+impl From<MemberVisibility> for Visibility {
+    fn from(visibility: MemberVisibility) -> Self {
+        match visibility {
+            MemberVisibility::Admins => Self::Admins,
+            MemberVisibility::Members => Self::Members,
+            MemberVisibility::Public => Self::Public,
+        }
+    }
+}
+
+impl From<Visibility> for MemberVisibility {
+    fn from(visibility: Visibility) -> Self {
+        match visibility {
+            Visibility::Admins => Self::Admins,
+            Visibility::Members => Self::Members,
+            Visibility::Public => Self::Public,
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct MemberVisibilityRequest {
+    pub username: String,
+    pub group_name: String,
+    pub visibility: Visibility,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MemberResponse {
+    pub username: String,
+    pub role: RoleResponse,
+    pub joined_at: DateTime<Utc>,
+}
+
+impl From<(Member, User)> for MemberResponse {
+    fn from((member, user): (Member, User)) -> Self {
+        Self {
+            username: user.name,
+            role: member.urole.into(),
+            joined_at: member.created_at,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MyGroupResponse {
+    pub group_name: String,
+    pub role: RoleResponse,
+    pub is_closed: bool,
+    pub member_count: i64,
+    /// Whether the draw gave this user a recipient, see `/get-recipient-name`
+    pub has_recipient: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MyGroupsResponse {
+    pub groups: Vec<MyGroupResponse>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct GroupDetailsResponse {
+    pub group_name: String,
+    pub is_closed: bool,
+    pub member_visibility: Visibility,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub members: Vec<MemberResponse>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct HealthResponse {
+    pub status: String,
+}
+
+#[derive(Serialize, ToSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Pending,
+    Failed,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReadinessChecks {
+    pub database: CheckStatus,
+    pub migrations: CheckStatus,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReadinessResponse {
+    pub ready: bool,
+    pub checks: ReadinessChecks,
+}
+
+impl ReadinessResponse {
+    pub fn new(database: CheckStatus, migrations: CheckStatus) -> Self {
+        Self {
+            ready: database == CheckStatus::Ok && migrations == CheckStatus::Ok,
+            checks: ReadinessChecks { database, migrations },
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportRowStatus {
+    /// The user was registered and added to the group.
+    Created,
+    /// An existing user was added to the group.
+    Added,
+    Skipped,
+    Failed,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ImportRowReport {
+    /// Line of the CSV, counting the header as line 1.
+    pub line: u64,
+    pub username: Option<String>,
+    pub status: ImportRowStatus,
+    pub message: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ImportMembersResponse {
+    pub group_name: String,
+    pub rows: Vec<ImportRowReport>,
+}
+
+#[derive(Deserialize, ToSchema, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AssignmentsQuery {
+    pub username: String,
+    pub group_name: String,
+    /// `json` (the default) or `csv`
+    #[serde(default)]
+    #[param(inline)]
+    pub format: ExportFormat,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AssignmentResponse {
+    pub santa: String,
+    pub recipient: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AssignmentsResponse {
+    pub group_name: String,
+    pub assignments: Vec<AssignmentResponse>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct AddWebhookRequest {
+    pub username: String,
+    pub group_name: String,
+    pub url: String,
+    /// Key of the HMAC-SHA256 signature sent in `X-Santa-Signature`
+    pub secret: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct WebhookIdRequest {
+    pub username: String,
+    pub group_name: String,
+    pub webhook_id: i32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WebhookResponse {
+    pub id: i32,
+    /// `None` for webhooks receiving the events of every group
+    pub group_name: Option<String>,
+    pub url: String,
+    /// Imported from a dump without its secret: it gets no deliveries until it
+    /// is removed and registered again with a new secret
+    pub needs_secret: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WebhooksResponse {
+    pub webhooks: Vec<WebhookResponse>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GroupPageQuery {
+    pub username: String,
+    pub group_name: String,
+    /// Only entries with a smaller id, i.e. the `next_before` of the previous page
+    pub before: Option<i32>,
+    /// Page size, 50 by default and at most 200
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WebhookDeliveryResponse {
+    pub id: i32,
+    pub webhook_id: i32,
+    pub url: String,
+    pub event: String,
+    pub attempt: i32,
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub success: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WebhookDeliveriesResponse {
+    /// Newest first
+    pub deliveries: Vec<WebhookDeliveryResponse>,
+    /// Pass as `before` to get the next page; absent on the last page
+    pub next_before: Option<i32>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AuditEntryResponse {
+    pub id: i32,
+    /// Absent for operator commands
+    pub actor: Option<String>,
+    pub action: String,
+    /// User or webhook the action was aimed at
+    pub target: Option<String>,
+    /// `ok` or the error code the action failed with
+    pub outcome: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AuditLogResponse {
+    /// Newest first
+    pub entries: Vec<AuditEntryResponse>,
+    /// Pass as `before` to get the next page; absent on the last page
+    pub next_before: Option<i32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(day: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(2026, 12, day)
+    }
+
+    #[test]
+    fn checks_group_filters() {
+        let query = GroupsQuery {
+            draw_from: day(1),
+            draw_until: day(24),
+            min_budget: Some(10),
+            max_budget: Some(10),
+            ..Default::default()
+        };
+        assert!(query.check().is_ok());
+        assert!(GroupsQuery { draw_from: day(24), draw_until: day(1), ..Default::default() }.check().is_err());
+        assert!(GroupsQuery { min_budget: Some(20), max_budget: Some(10), ..Default::default() }.check().is_err());
+    }
+
+    #[test]
+    fn checks_group_settings() {
+        let settings: GroupSettings = serde_json::from_str(r#"{"capacity": 3, "draw_date": "2026-12-20"}"#).unwrap();
+        assert_eq!(settings.draw_date, day(20));
+        assert!(settings.check().is_ok());
+        assert!(GroupSettings { capacity: Some(2), ..settings.clone() }.check().is_err());
+        assert!(GroupSettings { budget: Some(-5), ..settings }.check().is_err());
+    }
+}
@@ -0,0 +1,217 @@
+use async_std::task_local;
+use log::kv::{Key, Value, VisitSource};
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_json::{json, Map};
+use std::cell::RefCell;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tide::{Middleware, Next, Request};
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Incoming request ids longer than this are replaced by a generated one.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Tide's built-in access log; replaced by the one written by [`RequestLogging`].
+const TIDE_ACCESS_LOG_TARGET: &str = "tide::log::middleware";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct RequestContext {
+    id: Option<String>,
+    user: Option<String>,
+}
+
+task_local! {
+    static CONTEXT: RefCell<RequestContext> = RefCell::new(RequestContext::default());
+}
+
+thread_local! {
+    /// Request a blocking worker thread is doing work for; see [`with_request_id`].
+    static THREAD_REQUEST_ID: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Id of the request handled by the current task or worker thread, if any.
+pub fn request_id() -> Option<String> {
+    CONTEXT.try_with(|context| context.borrow().id.clone())
+        .ok()
+        .flatten()
+        .or_else(|| THREAD_REQUEST_ID.with(|id| id.borrow().clone()))
+}
+
+/// Runs `f` on the current thread as part of request `id`, so that what it
+/// logs carries the id like the task that handed the work over.
+pub fn with_request_id<T>(id: Option<String>, f: impl FnOnce() -> T) -> T {
+    let previous = THREAD_REQUEST_ID.with(|current| current.replace(id));
+    let result = f();
+    THREAD_REQUEST_ID.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// Remembers which user the current request acts for, for the access log.
+pub fn set_user(username: &str) {
+    let _ = CONTEXT.try_with(|context| context.borrow_mut().user = Some(username.to_string()));
+}
+
+fn set_request_id(id: Option<String>) {
+    let _ = CONTEXT.try_with(|context| {
+        *context.borrow_mut() = RequestContext { id, user: None };
+    });
+}
+
+fn take_user() -> Option<String> {
+    CONTEXT.try_with(|context| context.borrow_mut().user.take()).ok().flatten()
+}
+
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Assigns every request an id, taken from `X-Request-Id` when the client sent a
+/// valid one, echoes it in the response and writes one access log line per request.
+pub struct RequestLogging;
+
+#[tide::utils::async_trait]
+impl<State: Clone + Send + Sync + 'static> Middleware<State> for RequestLogging {
+    async fn handle(&self, request: Request<State>, next: Next<'_, State>) -> tide::Result {
+        let start = Instant::now();
+        let id = request.header(REQUEST_ID_HEADER)
+            .map(|values| values.last().as_str().to_string())
+            .filter(|id| is_valid_request_id(id))
+            .unwrap_or_else(|| format!("{:016x}", rand::random::<u64>()));
+        let method = request.method().to_string();
+        let route = request.url().path().to_string();
+        set_request_id(Some(id.clone()));
+
+        let mut response = next.run(request).await;
+
+        let status = response.status() as u16;
+        let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+        let user = take_user();
+        let level = match status {
+            500..=599 => log::Level::Error,
+            400..=499 => log::Level::Warn,
+            _ => log::Level::Info,
+        };
+        log::log!(
+            level,
+            method = method.as_str(),
+            route = route.as_str(),
+            status = status,
+            latency_ms = latency_ms,
+            user = user.as_deref().unwrap_or("-");
+            "{method} {route} {status}"
+        );
+        response.insert_header(REQUEST_ID_HEADER, id);
+        set_request_id(None);
+        Ok(response)
+    }
+}
+
+struct Logger {
+    format: LogFormat,
+    level: LevelFilter,
+}
+
+struct JsonFields<'a>(&'a mut Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+        let value = match (value.to_u64(), value.to_i64(), value.to_f64(), value.to_bool()) {
+            (Some(v), _, _, _) => json!(v),
+            (_, Some(v), _, _) => json!(v),
+            (_, _, Some(v), _) => json!(v),
+            (_, _, _, Some(v)) => json!(v),
+            _ => json!(value.to_string()),
+        };
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+struct TextFields<'a>(&'a mut String);
+
+impl<'kvs> VisitSource<'kvs> for TextFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+        self.0.push_str(&format!(" {key}={value}"));
+        Ok(())
+    }
+}
+
+impl Logger {
+    fn format_json(&self, record: &Record) -> String {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or_default();
+        let mut fields = Map::new();
+        fields.insert("timestamp".to_string(), json!(timestamp));
+        fields.insert("level".to_string(), json!(record.level().as_str()));
+        fields.insert("target".to_string(), json!(record.target()));
+        if let Some(id) = request_id() {
+            fields.insert("request_id".to_string(), json!(id));
+        }
+        fields.insert("message".to_string(), json!(record.args().to_string()));
+        let _ = record.key_values().visit(&mut JsonFields(&mut fields));
+        serde_json::Value::Object(fields).to_string()
+    }
+
+    fn format_text(&self, record: &Record) -> String {
+        let mut line = format!("{:<5} {}", record.level(), record.target());
+        if let Some(id) = request_id() {
+            line.push_str(&format!(" [{id}]"));
+        }
+        line.push_str(&format!(" {}", record.args()));
+        let _ = record.key_values().visit(&mut TextFields(&mut line));
+        line
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level && metadata.target() != TIDE_ACCESS_LOG_TARGET
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = match self.format {
+            LogFormat::Text => self.format_text(record),
+            LogFormat::Json => self.format_json(record),
+        };
+        let _ = writeln!(std::io::stdout().lock(), "{line}");
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stdout().flush();
+    }
+}
+
+/// Installs the global logger; call once at startup.
+pub fn init(format: LogFormat, level: LevelFilter) {
+    let logger = Logger { format, level };
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(level);
+    }
+}
@@ -1,224 +1,316 @@
+mod blocking;
+mod cli;
+mod config;
 mod database;
+mod dump;
+mod handlers;
 mod json_models;
+mod logging;
+mod member_import;
+mod metrics;
 mod models;
+mod notifications;
+mod openapi;
+mod pagination;
 mod schema;
+mod webhooks;
 mod errors;
+mod events;
 
+use crate::cli::Command;
+use crate::config::Config;
 use crate::database::Database;
-use crate::json_models::*;
-use serde_json::json;
-use std::sync::{Arc, RwLock};
-use tide::{log, Request, Response, StatusCode};
-
-fn make_response_from_result(result: Result<String, tide::Error>) -> Response {
-    match result {
-        Ok(s) => {
-            let mut response = Response::new(StatusCode::Ok);
-            response.set_body(s);
-            response
+use crate::dump::Dump;
+use crate::json_models::{GroupsQuery, GroupsResponse};
+use crate::notifications::Notifier;
+use serde::Serialize;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use tide::{log, Redirect, Request};
+use tide_rustls::TlsListener;
+
+/// Plain HTTP server answering every request with a redirect to the HTTPS port.
+fn https_redirect_server(https_port: u16) -> tide::Server<()> {
+    let mut server = tide::new();
+    let redirect = move |request: Request<()>| async move {
+        let url = request.url();
+        let host = url.host_str().unwrap_or("localhost");
+        let mut location = match https_port {
+            443 => format!("https://{host}{}", url.path()),
+            port => format!("https://{host}:{port}{}", url.path()),
+        };
+        if let Some(query) = url.query() {
+            location.push('?');
+            location.push_str(query);
         }
-        Err(e) => {
-            let mut response = Response::new(e.status());
-            let msg = e.into_inner().to_string();
-            log::debug!("Error: {msg}");
-            response.set_body(json!({"error_message": msg}).to_string());
-            response
+        Ok(Redirect::permanent(location))
+    };
+    server.at("/").all(redirect);
+    server.at("*").all(redirect);
+    server
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let cli = cli::parse();
+    let config = Config::from_args(cli.args)?;
+    logging::init(config.log_format, config.log_level);
+
+    let database = Database::new(&config)?
+        .with_notifier(Notifier::new(config.mail.as_ref())?);
+
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(config, database),
+        Command::Migrate => migrate(&database),
+        Command::CreateUser { username, email } => {
+            print_json(database.create_user(&username, email.as_deref())?)
+        }
+        Command::ListGroups { all: true } => print_json(database.get_all_groups()?),
+        Command::ListGroups { all: false } => print_json(list_open_groups(&database)?),
+        Command::InspectGroup { group_name } => print_json(database.inspect_group(&group_name)?),
+        Command::ForceClose { group_name } => print_json(database.force_close_group(&group_name)?),
+        Command::DeleteUser { username } => print_json(database.delete_user(&username)?),
+        Command::ImportMembers { group_name, file } => {
+            print_json(database.import_members(&group_name, &fs::read_to_string(file)?)?)
         }
+        Command::AddWebhook { url, secret, group } => {
+            print_json(database.register_webhook(group.as_deref(), &url, &secret)?)
+        }
+        Command::ListWebhooks => print_json(database.get_all_webhooks()?),
+        Command::DeleteWebhook { id } => print_json(database.unregister_webhook(id)?),
+        Command::Export { output, include_secrets } => export(&database, output, include_secrets),
+        Command::Import { file, force } => import(&database, &file, force),
+    }
+}
+
+fn export(
+    database: &Database,
+    output: Option<PathBuf>,
+    include_secrets: bool,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let dump = database.export_data(include_secrets)?;
+    match output {
+        Some(path) => serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), &dump)?,
+        None => print_json(dump)?,
+    }
+    Ok(())
+}
+
+fn import(database: &Database, file: &Path, force: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let dump: Dump = serde_json::from_reader(BufReader::new(File::open(file)?))?;
+    print_json(database.import_data(&dump, force)?)
+}
+
+/// Every open group, page by page.
+fn list_open_groups(database: &Database) -> Result<GroupsResponse, Box<dyn Error + Send + Sync>> {
+    let mut query = GroupsQuery::default();
+    let mut groups = Vec::new();
+    loop {
+        let page = database.get_open_groups(&query)?;
+        groups.extend(page.groups);
+        match page.next_cursor {
+            Some(cursor) => query.cursor = Some(cursor),
+            None => return Ok(GroupsResponse { groups, next_cursor: None }),
+        }
+    }
+}
+
+fn print_json<T: Serialize>(value: T) -> Result<(), Box<dyn Error + Send + Sync>> {
+    println!("{}", serde_json::to_string_pretty(&value)?);
+    Ok(())
+}
+
+fn migrate(database: &Database) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let applied = database.run_migrations()?;
+    match applied.is_empty() {
+        true => println!("Database schema is up to date"),
+        false => println!("Applied migrations: {}", applied.join(", ")),
+    }
+    Ok(())
+}
+
+/// The HTTP service with every route.
+fn app(database: Database) -> tide::Server<handlers::State> {
+    let mut app = tide::with_state(database);
+    app.with(logging::RequestLogging);
+    app.with(metrics::RequestMetrics);
+
+    for route in handlers::routes() {
+        app.at(route.path).method(route.method, route.endpoint);
     }
+    app
 }
 
-fn main() -> Result<(), std::io::Error> {
-    let version: &'static str = env!("CARGO_PKG_VERSION");
-    log::with_level(log::LevelFilter::Debug);
+fn serve(config: Config, database: Database) -> Result<(), Box<dyn Error + Send + Sync>> {
+    config.check_files()?;
+    match config.auto_migrate {
+        true => {
+            for version in database.run_migrations()? {
+                log::info!("Applied migration {version}");
+            }
+        }
+        false => database.check_schema_version()?,
+    }
 
     let f = async {
-        let database = Database;
-        let state = Arc::new(RwLock::new(database));
-        let mut app = tide::with_state(state);
-
-        app.at("/version")
-            .get(move |_| async move { Ok(format!("version: {version}")) });
-        app.at("/registr-user")
-            .post(|mut request: Request<Arc<RwLock<Database>>>| async move {
-                let jreq = request.body_json().await;
-                let Username { username } = match jreq {
-                    Ok(v) => v,
-                    Err(_) => {
-                        return Ok(make_response_from_result(
-                            Err(errors::error_bad_request("Incorect request".to_string()))
-                        ))
-                    }
-                };
-
-                let state = request.state();
-                let guard = state.write().unwrap();
-
-                Ok(make_response_from_result(
-                    guard.create_user(username.as_str())
-                ))
-            });
-        app.at("/create-group")
-            .post(|mut request: Request<Arc<RwLock<Database>>>| async move {
-                let jreq = request.body_json().await;
-                let UserGroupName { username, group_name } = match jreq {
-                    Ok(v) => v,
-                    Err(_) => {
-                        return Ok(make_response_from_result(
-                            Err(errors::error_bad_request("Incorect request".to_string()))
-                        ))
-                    }
-                };
-
-                let state = request.state();
-                let guard = state.write().unwrap();
-
-                Ok(make_response_from_result(
-                    guard.create_group_by_user(username.as_str(), group_name.as_str())
-                ))
-            });
-        app.at("/join-group")
-            .post(|mut request: Request<Arc<RwLock<Database>>>| async move {
-                let jreq = request.body_json().await;
-                let UserGroupName { username, group_name } = match jreq {
-                    Ok(v) => v,
-                    Err(_) => {
-                        return Ok(make_response_from_result(
-                            Err(errors::error_bad_request("Incorect request".to_string()))
-                        ))
-                    }
-                };
-
-                let state = request.state();
-                let guard = state.write().unwrap();
-
-                Ok(make_response_from_result(
-                    guard.add_user_to_group(username.as_str(), group_name.as_str())
-                ))
-            });
-        app.at("/delete-group")
-            .post(|mut request: Request<Arc<RwLock<Database>>>| async move {
-                let jreq = request.body_json().await;
-                let UserGroupName { username, group_name } = match jreq {
-                    Ok(v) => v,
-                    Err(_) => {
-                        return Ok(make_response_from_result(
-                            Err(errors::error_bad_request("Incorect request".to_string()))
-                        ))
-                    }
-                };
-
-                let state = request.state();
-                let guard = state.write().unwrap();
-
-                Ok(make_response_from_result(
-                    guard.delete_group_by_admin(username.as_str(), group_name.as_str())
-                ))
-            });
-        app.at("/group-members")
-            .get(|mut request: Request<Arc<RwLock<Database>>>| async move {
-                let jreq = request.body_json().await;
-                let UserGroupName { username, group_name } = match jreq {
-                    Ok(v) => v,
-                    Err(_) => {
-                        return Ok(make_response_from_result(
-                            Err(errors::error_bad_request("Incorect request".to_string()))
-                        ))
-                    }
-                };
-
-                let state = request.state();
-                let guard = state.read().unwrap();
-
-                Ok(make_response_from_result(
-                    guard.get_group_members(username.as_str(), group_name.as_str())
-                ))
-            });
-        app.at("/get-recipient-name")
-            .get(|mut request: Request<Arc<RwLock<Database>>>| async move {
-                let jreq = request.body_json().await;
-                let UserGroupName { username, group_name } = match jreq {
-                    Ok(v) => v,
-                    Err(_) => {
-                        return Ok(make_response_from_result(
-                            Err(errors::error_bad_request("Incorect request".to_string()))
-                        ))
-                    }
-                };
-
-                let state = request.state();
-                let guard = state.read().unwrap();
-
-                Ok(make_response_from_result(
-                    guard.get_recipient_name(username.as_str(), group_name.as_str())
-                ))
-            });
-        app.at("/add-admin")
-            .post(|mut request: Request<Arc<RwLock<Database>>>| async move {
-                let jreq = request.body_json().await;
-                let UserGroupNewAdminName { username, group_name, new_admin } = match jreq {
-                    Ok(v) => v,
-                    Err(_) => {
-                        return Ok(make_response_from_result(
-                            Err(errors::error_bad_request("Incorect request".to_string()))
-                        ))
+        let app = app(database);
+        match &config.tls {
+            Some(tls) => {
+                let listener = TlsListener::build()
+                    .addrs(config.listen_address())
+                    .cert(&tls.cert)
+                    .key(&tls.key);
+                match config.redirect_address() {
+                    Some(redirect_address) => {
+                        let redirect = https_redirect_server(config.port);
+                        futures::try_join!(app.listen(listener), redirect.listen(redirect_address))
+                            .map(|_| ())
                     }
-                };
-
-                let state = request.state();
-                let guard = state.write().unwrap();
-
-                Ok(make_response_from_result(
-                    guard.add_admin_to_group(username.as_str(), new_admin.as_str(), group_name.as_str())
-                ))
-            });
-        app.at("/start-secret-santa")
-            .post(|mut request: Request<Arc<RwLock<Database>>>| async move {
-                let jreq = request.body_json().await;
-                let UserGroupName { username, group_name } = match jreq {
-                    Ok(v) => v,
-                    Err(_) => {
-                        return Ok(make_response_from_result(
-                            Err(errors::error_bad_request("Incorect request".to_string()))
-                        ))
-                    }
-                };
-
-                let state = request.state();
-                let guard = state.write().unwrap();
-
-                Ok(make_response_from_result(
-                    guard.close_group(username.as_str(), group_name.as_str())
-                ))
-            });
-        app.at("/revoke-admin-rights")
-            .post(|mut request: Request<Arc<RwLock<Database>>>| async move {
-                let jreq = request.body_json().await;
-                let UserGroupName { username, group_name } = match jreq {
-                    Ok(v) => v,
-                    Err(_) => {
-                        return Ok(make_response_from_result(
-                            Err(errors::error_bad_request("Incorect request".to_string()))
-                        ))
-                    }
-                };
-
-                let state = request.state();
-                let guard = state.write().unwrap();
-
-                Ok(make_response_from_result(
-                    guard.revoke_rights_of_admin(username.as_str(), group_name.as_str())
-                ))
-            });
-        app.at("/get-groups")
-            .get(|request: Request<Arc<RwLock<Database>>>| async move {
-                let state = request.state();
-                let guard = state.read().unwrap();
-
-                Ok(make_response_from_result(
-                    guard.get_open_groups()
-                ))
-            });
-        app.listen("127.0.0.1:80").await
+                    None => app.listen(listener).await,
+                }
+            }
+            None => app.listen(config.listen_address()).await,
+        }
     };
-    futures::executor::block_on(f)
+    async_std::task::block_on(f)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::r2d2::{ConnectionManager, Pool};
+    use diesel::PgConnection;
+    use serde_json::json;
+    use std::time::Instant;
+    use tide::http::{Method, Url};
+
+    /// Numbers of concurrent clients the load test is run with.
+    const LOAD_CLIENTS: [usize; 5] = [1, 2, 4, 8, 16];
+    /// Iterations per run, split evenly between the clients; each iteration
+    /// registers a user, joins the group and lists the user's groups.
+    const LOAD_ITERATIONS: usize = 240;
+
+    async fn call(app: &tide::Server<handlers::State>, method: Method, path: &str, body: serde_json::Value) {
+        let mut request = tide::http::Request::new(method, Url::parse("http://localhost/").unwrap().join(path).unwrap());
+        if method == Method::Post {
+            request.set_body(tide::Body::from_json(&body).unwrap());
+        }
+        let mut response: tide::http::Response = app.respond(request).await.unwrap();
+        assert!(
+            response.status().is_success(),
+            "{method} {path}: {} {}",
+            response.status(),
+            response.body_string().await.unwrap()
+        );
+    }
+
+    /// Deletes what the load test created when it ends, also when it fails midway.
+    struct Cleanup {
+        database: Database,
+        admin: String,
+        group_name: String,
+        usernames: Vec<String>,
+    }
+
+    impl Drop for Cleanup {
+        fn drop(&mut self) {
+            // A failed run may not have got to all of them, so errors are expected.
+            let _ = self.database.delete_group_by_admin(&self.admin, &self.group_name);
+            for username in self.usernames.iter().chain([&self.admin]) {
+                let _ = self.database.delete_user(username);
+            }
+        }
+    }
+
+    /// Sends requests from a growing number of concurrent clients through the
+    /// whole service, routing included, checks that every client joined the
+    /// group, and prints the throughput of each run.
+    /// Needs a scratch database. Unlike the benchmarks, it commits what it
+    /// creates, so every name starts with a prefix unique to the run and
+    /// everything is deleted at the end, even if the test fails:
+    ///
+    /// `SANTA_BENCH_DATABASE_URL=postgres://... cargo test --release -- --ignored --nocapture load_test`
+    #[test]
+    #[ignore]
+    fn load_test() {
+        let url = std::env::var("SANTA_BENCH_DATABASE_URL").expect("SANTA_BENCH_DATABASE_URL must be set");
+        let pool_size = *LOAD_CLIENTS.iter().max().unwrap() as u32;
+        let pool = Pool::builder()
+            .max_size(pool_size)
+            .build(ConnectionManager::<PgConnection>::new(url))
+            .unwrap();
+        let database = Database::from_pool(pool, pool_size);
+        database.run_migrations().unwrap();
+        let app = app(database.clone());
+
+        let run = format!("load-{}", chrono::Utc::now().timestamp_millis());
+        // The usernames of every client in every run, created as the runs go.
+        let usernames: Vec<Vec<Vec<String>>> = LOAD_CLIENTS.iter()
+            .map(|&clients| {
+                (0..clients)
+                    .map(|client| {
+                        (0..LOAD_ITERATIONS / clients)
+                            .map(|i| format!("{run}-{clients}-{client}-{i}"))
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+        let cleanup = Cleanup {
+            database: database.clone(),
+            admin: format!("{run}-admin"),
+            group_name: run.clone(),
+            usernames: usernames.iter().flatten().flatten().cloned().collect(),
+        };
+        database.create_user(&cleanup.admin, None).unwrap();
+        database.create_group_by_user(&cleanup.admin, &run, &Default::default()).unwrap();
+
+        let mut baseline = None;
+        let mut joined = 0;
+        for (clients, usernames) in LOAD_CLIENTS.into_iter().zip(usernames) {
+            let per_client = LOAD_ITERATIONS / clients;
+            let start = Instant::now();
+            let tasks: Vec<_> = usernames.into_iter()
+                .map(|usernames| {
+                    let app = app.clone();
+                    let run = run.clone();
+                    async_std::task::spawn(async move {
+                        let mut joins = 0;
+                        for username in usernames {
+                            let user_group = json!({ "username": username, "group_name": run });
+                            call(&app, Method::Post, "registr-user", json!({ "username": username })).await;
+                            call(&app, Method::Post, "join-group", user_group).await;
+                            joins += 1;
+                            call(&app, Method::Get, &format!("my-groups?username={username}"), json!(null)).await;
+                        }
+                        joins
+                    })
+                })
+                .collect();
+            let joins = async_std::task::block_on(futures::future::join_all(tasks));
+            let elapsed = start.elapsed().as_secs_f64();
+
+            assert_eq!(joins, vec![per_client; clients], "{clients} clients: joins per client");
+            joined += clients * per_client;
+            let members = database.inspect_group(&run).unwrap().members.len();
+            assert_eq!(members, joined + 1, "{clients} clients: members, the admin included");
+
+            let requests_per_second = (3 * per_client * clients) as f64 / elapsed;
+            let baseline = *baseline.get_or_insert(requests_per_second);
+            println!(
+                "{clients:>2} clients: {requests_per_second:>7.0} requests/s ({:.1}x)",
+                requests_per_second / baseline
+            );
+        }
+        drop(cleanup);
+    }
 }
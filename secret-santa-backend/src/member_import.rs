@@ -0,0 +1,210 @@
+//! CSV accepted by `/import-members` and the `import-members` subcommand.
+//!
+//! The first line is a header; columns may come in any order. `username` is
+//! required, `exclude` lists usernames separated by `;` whom the member must
+//! not draw (and who must not draw them), and `wishlist` is a free-form note.
+//!
+//! ```text
+//! username,exclude,wishlist
+//! alice,bob,books
+//! bob,alice;carol,
+//! carol,,socks
+//! ```
+
+use crate::errors::ServiceError;
+use serde::Deserialize;
+use std::collections::HashSet;
+
+/// Largest number of data lines accepted in one import.
+pub const MAX_ROWS: usize = 5000;
+
+/// Length limit of `users.name`.
+const MAX_USERNAME_LEN: usize = 50;
+
+const EXCLUDE_SEPARATOR: char = ';';
+
+#[derive(Deserialize)]
+struct Record {
+    username: String,
+    #[serde(default)]
+    exclude: Option<String>,
+    #[serde(default)]
+    wishlist: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct MemberRow {
+    pub exclude: Vec<String>,
+    pub wishlist: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Rejection {
+    Skipped(String),
+    Failed(String),
+}
+
+/// One data line of the CSV: either a member to add or the reason it isn't added.
+#[derive(Debug)]
+pub struct ImportRow {
+    pub line: u64,
+    pub username: Option<String>,
+    pub outcome: Result<MemberRow, Rejection>,
+}
+
+fn failed(line: u64, username: Option<String>, message: String) -> ImportRow {
+    ImportRow { line, username, outcome: Err(Rejection::Failed(message)) }
+}
+
+/// Reads every data line; lines that can't be used are kept as failed rows so
+/// that they show up in the report. Only an unusable header fails the whole import.
+pub fn parse(input: &str) -> Result<Vec<ImportRow>, ServiceError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(input.as_bytes());
+    let headers = reader.headers()
+        .map_err(|e| ServiceError::BadRequest(format!("Cannot read CSV header: {e}")))?
+        .clone();
+    if !headers.iter().any(|header| header == "username") {
+        return Err(ServiceError::BadRequest("CSV header must contain a username column".to_string()));
+    }
+
+    let mut rows = Vec::new();
+    for result in reader.records() {
+        if rows.len() == MAX_ROWS {
+            return Err(ServiceError::BadRequest(format!("CSV has more than {MAX_ROWS} rows")));
+        }
+        let record = match result {
+            Ok(record) => record,
+            Err(e) => {
+                let line = e.position().map(|position| position.line()).unwrap_or_default();
+                rows.push(failed(line, None, format!("Cannot read line: {e}")));
+                continue;
+            }
+        };
+        let line = record.position().map(|position| position.line()).unwrap_or_default();
+        if record.iter().all(str::is_empty) {
+            continue;
+        }
+        let Record { username, exclude, wishlist } = match record.deserialize(Some(&headers)) {
+            Ok(v) => v,
+            Err(e) => {
+                rows.push(failed(line, None, format!("Cannot read line: {e}")));
+                continue;
+            }
+        };
+        rows.push(parse_row(line, username, exclude, wishlist));
+    }
+    Ok(rows)
+}
+
+fn parse_row(
+    line: u64,
+    username: String,
+    exclude: Option<String>,
+    wishlist: Option<String>,
+) -> ImportRow {
+    if username.is_empty() {
+        return failed(line, None, "Username is empty".to_string());
+    }
+    if username.chars().count() > MAX_USERNAME_LEN {
+        return failed(line, Some(username), format!("Username is longer than {MAX_USERNAME_LEN} characters"));
+    }
+    let exclude: Vec<String> = exclude.unwrap_or_default()
+        .split(EXCLUDE_SEPARATOR)
+        .map(str::trim)
+        .filter(|partner| !partner.is_empty())
+        .map(str::to_string)
+        .collect();
+    if exclude.contains(&username) {
+        return failed(line, Some(username), "A member can't exclude themselves".to_string());
+    }
+    let wishlist = wishlist.filter(|wishlist| !wishlist.is_empty());
+    ImportRow { line, username: Some(username), outcome: Ok(MemberRow { exclude, wishlist }) }
+}
+
+/// Skips repeated usernames and users who already are members, then fails rows
+/// whose exclusion partners won't be members after the import. Failing a row can
+/// strand exclusions of other rows, so this repeats until nothing changes.
+pub fn check(rows: &mut [ImportRow], is_member: impl Fn(&str) -> bool) {
+    let mut seen = HashSet::new();
+    for row in rows.iter_mut() {
+        let Some(username) = row.username.clone() else { continue };
+        if row.outcome.is_err() {
+            continue;
+        }
+        if !seen.insert(username.clone()) {
+            row.outcome = Err(Rejection::Skipped("Duplicate of an earlier line".to_string()));
+        } else if is_member(&username) {
+            row.outcome = Err(Rejection::Skipped("Already a member of the group".to_string()));
+        }
+    }
+
+    let mut available: HashSet<String> = rows.iter()
+        .filter(|row| row.outcome.is_ok())
+        .filter_map(|row| row.username.clone())
+        .collect();
+    loop {
+        let mut changed = false;
+        for row in rows.iter_mut() {
+            let Ok(member) = &row.outcome else { continue };
+            let missing = member.exclude.iter()
+                .find(|partner| !available.contains(*partner) && !is_member(partner));
+            if let Some(partner) = missing {
+                let message = format!("Exclusion partner {partner} is not a member of the group");
+                row.outcome = Err(Rejection::Failed(message));
+                if let Some(username) = &row.username {
+                    available.remove(username);
+                }
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcomes(rows: &[ImportRow]) -> Vec<(Option<&str>, &Result<MemberRow, Rejection>)> {
+        rows.iter().map(|row| (row.username.as_deref(), &row.outcome)).collect()
+    }
+
+    #[test]
+    fn parses_optional_columns_in_any_order() {
+        let rows = parse("wishlist,username\nbooks,alice\n,bob\n").unwrap();
+        assert_eq!(outcomes(&rows), vec![
+            (Some("alice"), &Ok(MemberRow { exclude: vec![], wishlist: Some("books".to_string()) })),
+            (Some("bob"), &Ok(MemberRow { exclude: vec![], wishlist: None })),
+        ]);
+        assert_eq!(rows[1].line, 3);
+    }
+
+    #[test]
+    fn rejects_header_without_username() {
+        assert!(parse("name,wishlist\nalice,books\n").is_err());
+    }
+
+    #[test]
+    fn reports_invalid_rows() {
+        let rows = parse("username,exclude\n,bob\nalice, alice ; bob\n").unwrap();
+        assert!(matches!(rows[0].outcome, Err(Rejection::Failed(_))));
+        assert!(matches!(rows[1].outcome, Err(Rejection::Failed(_))));
+    }
+
+    #[test]
+    fn check_skips_duplicates_and_members_and_cascades_failures() {
+        let mut rows = parse("username,exclude\nalice,bob\nbob,carol\ndave,erin\ndave,\nerin,\n").unwrap();
+        check(&mut rows, |name| name == "erin");
+
+        assert!(matches!(rows[0].outcome, Err(Rejection::Failed(_))));
+        assert!(matches!(rows[1].outcome, Err(Rejection::Failed(_))));
+        assert!(rows[2].outcome.is_ok());
+        assert!(matches!(rows[3].outcome, Err(Rejection::Skipped(_))));
+        assert!(matches!(rows[4].outcome, Err(Rejection::Skipped(_))));
+    }
+}
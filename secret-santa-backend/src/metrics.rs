@@ -0,0 +1,122 @@
+use crate::openapi::ApiDoc;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramTimer, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec,
+    Opts, Registry, TextEncoder,
+};
+use std::collections::HashSet;
+use std::sync::OnceLock;
+use std::time::Instant;
+use tide::{Middleware, Next, Request};
+use utoipa::OpenApi;
+
+/// Route label for requests that don't match a documented route,
+/// so that scanners can't blow up the label cardinality.
+const UNKNOWN_ROUTE: &str = "unknown";
+
+pub struct Metrics {
+    registry: Registry,
+    routes: HashSet<String>,
+    http_requests: IntCounterVec,
+    http_request_seconds: HistogramVec,
+    db_query_seconds: HistogramVec,
+    groups: IntGaugeVec,
+    members: IntGauge,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("secret_santa".to_string()), None)
+            .expect("metrics prefix is valid");
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by route, method and status"),
+            &["route", "method", "status"],
+        ).expect("metric is valid");
+        let http_request_seconds = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "HTTP request latency"),
+            &["route", "method", "status"],
+        ).expect("metric is valid");
+        let db_query_seconds = HistogramVec::new(
+            HistogramOpts::new("db_query_duration_seconds", "Database query latency by query")
+                .buckets(vec![0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0]),
+            &["query"],
+        ).expect("metric is valid");
+        let groups = IntGaugeVec::new(
+            Opts::new("groups", "Number of groups by state"),
+            &["state"],
+        ).expect("metric is valid");
+        let members = IntGauge::new("members", "Number of group memberships")
+            .expect("metric is valid");
+
+        registry.register(Box::new(http_requests.clone())).expect("metric is registered once");
+        registry.register(Box::new(http_request_seconds.clone())).expect("metric is registered once");
+        registry.register(Box::new(db_query_seconds.clone())).expect("metric is registered once");
+        registry.register(Box::new(groups.clone())).expect("metric is registered once");
+        registry.register(Box::new(members.clone())).expect("metric is registered once");
+
+        Self {
+            registry,
+            routes: ApiDoc::openapi().paths.paths.into_keys().collect(),
+            http_requests,
+            http_request_seconds,
+            db_query_seconds,
+            groups,
+            members,
+        }
+    }
+
+    fn route_label<'a>(&self, path: &'a str) -> &'a str {
+        match self.routes.contains(path) {
+            true => path,
+            false => UNKNOWN_ROUTE,
+        }
+    }
+
+    /// Starts timing a database query; the time is recorded when the timer is dropped.
+    pub fn db_timer(&self, query: &str) -> HistogramTimer {
+        self.db_query_seconds.with_label_values(&[query]).start_timer()
+    }
+
+    pub fn set_group_counts(&self, open: i64, closed: i64, members: i64) {
+        self.groups.with_label_values(&["open"]).set(open);
+        self.groups.with_label_values(&["closed"]).set(closed);
+        self.members.set(members);
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        let encoder = TextEncoder::new();
+        encoder.encode(&self.registry.gather(), &mut buffer)
+            .expect("text encoding into memory does not fail");
+        String::from_utf8(buffer).expect("text encoder produces UTF-8")
+    }
+}
+
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+/// Counts every request and records its latency per route, method and status.
+pub struct RequestMetrics;
+
+#[tide::utils::async_trait]
+impl<State: Clone + Send + Sync + 'static> Middleware<State> for RequestMetrics {
+    async fn handle(&self, request: Request<State>, next: Next<'_, State>) -> tide::Result {
+        let start = Instant::now();
+        let metrics = metrics();
+        let route = metrics.route_label(request.url().path()).to_string();
+        let method = request.method().to_string();
+
+        let response = next.run(request).await;
+
+        let status = (response.status() as u16).to_string();
+        let labels = [route.as_str(), method.as_str(), status.as_str()];
+        metrics.http_requests.with_label_values(&labels).inc();
+        metrics.http_request_seconds
+            .with_label_values(&labels)
+            .observe(start.elapsed().as_secs_f64());
+        Ok(response)
+    }
+}
@@ -1,4 +1,5 @@
-use crate::schema::{members, santas, sgroups, users};
+use crate::schema::{audit_log, exclusions, members, santas, sgroups, users, webhook_deliveries, webhooks};
+use chrono::{DateTime, NaiveDate, Utc};
 use diesel::prelude::*;
 use diesel::sql_types::Integer;
 use diesel::{AsChangeset, AsExpression, FromSqlRow, Queryable};
@@ -9,6 +10,7 @@ use serde::Serialize;
 #[diesel(table_name = users)]
 pub struct NewUser<'a> {
     pub name: &'a str,
+    pub email: Option<&'a str>,
 }
 
 #[derive(Debug, Queryable, AsChangeset, Serialize)]
@@ -16,12 +18,18 @@ pub struct NewUser<'a> {
 pub struct User {
     pub id: i32,
     pub name: String,
+    pub email: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
 }
 
 #[derive(Insertable)]
 #[diesel(table_name = sgroups)]
 pub struct NewGroup<'a> {
     pub gname: &'a str,
+    pub capacity: Option<i32>,
+    pub draw_date: Option<NaiveDate>,
+    pub budget: Option<i32>,
 }
 
 #[derive(Debug, Queryable, AsChangeset, Serialize)]
@@ -30,15 +38,18 @@ pub struct Group {
     pub id: i32,
     pub gname: String,
     pub is_close: bool,
+    pub is_revealed: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub capacity: Option<i32>,
+    pub draw_date: Option<NaiveDate>,
+    pub budget: Option<i32>,
+    pub member_visibility: MemberVisibility,
 }
 
 impl Group {
     pub fn close_group(self) -> Self {
-        Self {
-            id: self.id,
-            gname: self.gname,
-            is_close: true,
-        }
+        Self { is_close: true, ..self }
     }
 }
 
@@ -64,12 +75,25 @@ pub enum Role {
     Admin,
 }
 
+/// Who may list the members of a group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, DbEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[sql_type = "Integer"]
+#[error_fn = "ConversionError::not_found"]
+#[error_type = "ConversionError"]
+pub enum MemberVisibility {
+    Admins,
+    Members,
+    Public,
+}
+
 #[derive(Insertable)]
 #[diesel(table_name = members)]
-pub struct NewMember {
+pub struct NewMember<'a> {
     pub user_id: i32,
     pub group_id: i32,
     pub urole: Role,
+    pub wishlist: Option<&'a str>,
 }
 
 #[derive(Debug, Queryable, AsChangeset)]
@@ -79,16 +103,14 @@ pub struct Member {
     pub user_id: i32,
     pub group_id: i32,
     pub urole: Role,
+    pub wishlist: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
 }
 
 impl Member {
     pub fn set_role(self, new_role: Role) -> Self {
-        Self {
-            id: self.id,
-            user_id: self.user_id,
-            group_id: self.group_id,
-            urole: new_role,
-        }
+        Self { urole: new_role, ..self }
     }
 }
 
@@ -107,4 +129,124 @@ pub struct Santa {
     pub group_id: i32,
     pub santa_id: i32,
     pub recipient_id: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = exclusions)]
+pub struct NewExclusion {
+    pub group_id: i32,
+    pub user_id: i32,
+    pub excluded_user_id: i32,
+}
+
+#[derive(Debug, Queryable, AsChangeset)]
+#[diesel(table_name = exclusions)]
+pub struct Exclusion {
+    pub id: i32,
+    pub group_id: i32,
+    pub user_id: i32,
+    pub excluded_user_id: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = webhooks)]
+pub struct NewWebhook<'a> {
+    pub group_id: Option<i32>,
+    pub url: &'a str,
+    pub secret: Option<&'a str>,
+}
+
+#[derive(Debug, Queryable, AsChangeset)]
+#[diesel(table_name = webhooks)]
+pub struct Webhook {
+    pub id: i32,
+    pub group_id: Option<i32>,
+    pub url: String,
+    /// `None` for webhooks imported without their secret, which need a new one.
+    pub secret: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = webhook_deliveries)]
+pub struct NewWebhookDelivery<'a> {
+    pub webhook_id: i32,
+    pub event: &'a str,
+    pub attempt: i32,
+    pub status_code: Option<i32>,
+    pub error: Option<&'a str>,
+    pub success: bool,
+}
+
+#[derive(Debug, Queryable)]
+#[diesel(table_name = webhook_deliveries)]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub webhook_id: i32,
+    pub event: String,
+    pub attempt: i32,
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub success: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Mutating operations recorded in the audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    CreateGroup,
+    JoinGroup,
+    AddAdmin,
+    RevokeAdmin,
+    CloseGroup,
+    DeleteGroup,
+    ImportMembers,
+    RevealAssignments,
+    SetMemberVisibility,
+    AddWebhook,
+    RemoveWebhook,
+    DeleteUser,
+}
+
+impl AuditAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::CreateGroup => "create_group",
+            Self::JoinGroup => "join_group",
+            Self::AddAdmin => "add_admin",
+            Self::RevokeAdmin => "revoke_admin",
+            Self::CloseGroup => "close_group",
+            Self::DeleteGroup => "delete_group",
+            Self::ImportMembers => "import_members",
+            Self::RevealAssignments => "reveal_assignments",
+            Self::SetMemberVisibility => "set_member_visibility",
+            Self::AddWebhook => "add_webhook",
+            Self::RemoveWebhook => "remove_webhook",
+            Self::DeleteUser => "delete_user",
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = audit_log)]
+pub struct NewAuditEntry<'a> {
+    pub group_id: Option<i32>,
+    pub group_name: Option<&'a str>,
+    pub actor: Option<&'a str>,
+    pub action: &'a str,
+    pub target: Option<&'a str>,
+    pub outcome: &'a str,
+}
+
+/// An entry as listed for its group.
+#[derive(Debug, Queryable)]
+#[diesel(table_name = audit_log)]
+pub struct AuditEntry {
+    pub id: i32,
+    pub actor: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub outcome: String,
+    pub created_at: DateTime<Utc>,
 }
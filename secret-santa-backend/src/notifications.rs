@@ -0,0 +1,219 @@
+//! Notification emails to members, e.g. when the draw of their group is complete.
+//!
+//! Messages are handed to a background thread that delivers them through a
+//! [`MailTransport`], so requests never wait for the mail server. Users without
+//! an email address are not notified.
+
+use crate::config::{MailConfig, MailTransportConfig};
+use crate::models::User;
+use lettre::message::header::ContentType;
+use lettre::message::{Mailbox, Message};
+use lettre::{Address, Transport};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tide::log;
+
+pub type MailError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Delivers a complete message to its recipients.
+pub trait MailTransport: Send {
+    fn send(&self, message: &Message) -> Result<(), MailError>;
+}
+
+pub struct SmtpTransport(lettre::SmtpTransport);
+
+impl SmtpTransport {
+    /// Connects lazily to the server given as `smtp://` or `smtps://` URL.
+    pub fn new(url: &str) -> Result<Self, MailError> {
+        Ok(Self(lettre::SmtpTransport::from_url(url)?.build()))
+    }
+}
+
+impl MailTransport for SmtpTransport {
+    fn send(&self, message: &Message) -> Result<(), MailError> {
+        self.0.send(message)?;
+        Ok(())
+    }
+}
+
+/// Writes every message as an `.eml` file into a directory.
+pub struct FileTransport {
+    dir: PathBuf,
+}
+
+impl FileTransport {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+impl MailTransport for FileTransport {
+    fn send(&self, message: &Message) -> Result<(), MailError> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let path = self.dir.join(format!("{timestamp}-{:08x}.eml", rand::random::<u32>()));
+        std::fs::write(path, message.formatted())?;
+        Ok(())
+    }
+}
+
+pub enum Notification<'a> {
+    DrawComplete { group_name: &'a str, recipient: &'a str },
+    AddedToGroup { group_name: &'a str },
+    RemovedFromGroup { group_name: &'a str },
+}
+
+impl Notification<'_> {
+    fn subject(&self) -> String {
+        match self {
+            Self::DrawComplete { group_name, .. } => format!("Secret Santa in {group_name}: your recipient"),
+            Self::AddedToGroup { group_name } => format!("You joined the Secret Santa group {group_name}"),
+            Self::RemovedFromGroup { group_name } => format!("You were removed from the Secret Santa group {group_name}"),
+        }
+    }
+
+    fn body(&self, username: &str) -> String {
+        match self {
+            Self::DrawComplete { group_name, recipient } => format!(
+                "Hello {username},\n\nthe draw in {group_name} is complete. You are the secret santa of {recipient}.\n"
+            ),
+            Self::AddedToGroup { group_name } => format!(
+                "Hello {username},\n\nyou are now a member of {group_name}. We'll let you know your recipient once the draw is done.\n"
+            ),
+            Self::RemovedFromGroup { group_name } => format!(
+                "Hello {username},\n\nyou are no longer a member of {group_name}.\n"
+            ),
+        }
+    }
+}
+
+/// Checks that `email` can be used as a recipient address.
+pub fn is_valid_email(email: &str) -> bool {
+    email.len() <= 254 && email.parse::<Address>().is_ok()
+}
+
+struct Worker {
+    from: Mailbox,
+    sender: Option<Sender<Message>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for Worker {
+    /// Delivers the queued messages before the process exits.
+    fn drop(&mut self) {
+        self.sender.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Queues notification emails; does nothing when mail is not configured.
+#[derive(Clone, Default)]
+pub struct Notifier {
+    worker: Option<Arc<Worker>>,
+}
+
+impl Notifier {
+    pub fn new(config: Option<&MailConfig>) -> Result<Self, MailError> {
+        let Some(config) = config else {
+            return Ok(Self::default());
+        };
+        let transport: Box<dyn MailTransport> = match &config.transport {
+            MailTransportConfig::Smtp(url) => Box::new(SmtpTransport::new(url)?),
+            MailTransportConfig::File(dir) => Box::new(FileTransport::new(dir.clone())),
+        };
+        Ok(Self::with_transport(config.from.clone(), transport))
+    }
+
+    pub fn with_transport(from: Mailbox, transport: Box<dyn MailTransport>) -> Self {
+        let (sender, receiver) = mpsc::channel::<Message>();
+        let handle = thread::spawn(move || {
+            for message in receiver {
+                if let Err(e) = transport.send(&message) {
+                    log::error!("Cannot send notification email: {e}");
+                }
+            }
+        });
+        Self {
+            worker: Some(Arc::new(Worker { from, sender: Some(sender), handle: Some(handle) })),
+        }
+    }
+
+    pub fn notify(&self, user: &User, notification: Notification) {
+        let Some(worker) = &self.worker else { return };
+        let Some(email) = &user.email else {
+            log::debug!("User {} has no email address, not notifying", user.name);
+            return;
+        };
+        let address = match email.parse::<Address>() {
+            Ok(address) => address,
+            Err(e) => {
+                log::warn!("Cannot notify user {}: {e}", user.name);
+                return;
+            }
+        };
+        let message = Message::builder()
+            .from(worker.from.clone())
+            .to(Mailbox::new(Some(user.name.clone()), address))
+            .subject(notification.subject())
+            .header(ContentType::TEXT_PLAIN)
+            .body(notification.body(&user.name));
+        match (message, &worker.sender) {
+            (Ok(message), Some(sender)) => {
+                if sender.send(message).is_err() {
+                    log::error!("Notification worker has stopped");
+                }
+            }
+            (Ok(_), None) => {}
+            (Err(e), _) => log::warn!("Cannot build notification email for user {}: {e}", user.name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_transport_writes_queued_messages() {
+        let dir = std::env::temp_dir().join(format!("santa-mail-{:08x}", rand::random::<u32>()));
+        std::fs::create_dir(&dir).unwrap();
+        let notifier = Notifier::with_transport(
+            "Santa <santa@localhost>".parse().unwrap(),
+            Box::new(FileTransport::new(dir.clone())),
+        );
+        let now = chrono::Utc::now();
+        let alice = User {
+            id: 1,
+            name: "alice".to_string(),
+            email: Some("alice@example.com".to_string()),
+            created_at: now,
+            updated_at: now,
+        };
+        let bob = User { id: 2, name: "bob".to_string(), email: None, created_at: now, updated_at: now };
+
+        notifier.notify(&alice, Notification::DrawComplete { group_name: "friends", recipient: "bob" });
+        notifier.notify(&bob, Notification::AddedToGroup { group_name: "friends" });
+        drop(notifier);
+
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+        assert_eq!(files.len(), 1);
+        let content = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(content.contains("To: alice <alice@example.com>"));
+        assert!(content.contains("You are the secret santa of bob."));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn validates_email_addresses() {
+        assert!(is_valid_email("alice@example.com"));
+        assert!(!is_valid_email("alice"));
+        assert!(!is_valid_email("alice@"));
+    }
+}
@@ -0,0 +1,79 @@
+use crate::handlers;
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Secret Santa service"),
+    paths(
+        handlers::version,
+        handlers::healthz,
+        handlers::readyz,
+        handlers::metrics,
+        handlers::openapi_json,
+        handlers::registr_user,
+        handlers::set_email,
+        handlers::create_group,
+        handlers::join_group,
+        handlers::delete_group,
+        handlers::my_groups,
+        handlers::group_members,
+        handlers::group_events,
+        handlers::set_member_visibility,
+        handlers::import_members,
+        handlers::get_recipient_name,
+        handlers::add_admin,
+        handlers::start_secret_santa,
+        handlers::revoke_admin_rights,
+        handlers::reveal_assignments,
+        handlers::export_assignments,
+        handlers::add_webhook,
+        handlers::remove_webhook,
+        handlers::webhooks,
+        handlers::webhook_deliveries,
+        handlers::audit_log,
+        handlers::get_groups,
+    )
+)]
+pub struct ApiDoc;
+
+#[cfg(test)]
+mod tests {
+    use super::ApiDoc;
+    use crate::handlers;
+    use std::collections::BTreeSet;
+    use tide::http::Method;
+    use utoipa::openapi::PathItem;
+    use utoipa::OpenApi;
+
+    /// Methods with an operation in `item`.
+    fn documented_methods(item: &PathItem) -> Vec<Method> {
+        [
+            (Method::Get, item.get.is_some()),
+            (Method::Post, item.post.is_some()),
+            (Method::Put, item.put.is_some()),
+            (Method::Delete, item.delete.is_some()),
+            (Method::Patch, item.patch.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(method, documented)| documented.then_some(method))
+        .collect()
+    }
+
+    #[test]
+    fn routes_match_the_document() {
+        let registered: BTreeSet<(String, String)> = handlers::routes().into_iter()
+            .map(|route| (route.path.to_string(), route.method.to_string()))
+            .collect();
+        let documented: BTreeSet<(String, String)> = ApiDoc::openapi().paths.paths.iter()
+            .flat_map(|(path, item)| {
+                documented_methods(item).into_iter().map(move |method| (path.clone(), method.to_string()))
+            })
+            .collect();
+        assert!(!registered.is_empty());
+
+        let undocumented: Vec<_> = registered.difference(&documented).collect();
+        assert!(undocumented.is_empty(), "missing from the OpenAPI document: {undocumented:?}");
+        let unregistered: Vec<_> = documented.difference(&registered).collect();
+        assert!(unregistered.is_empty(), "documented but not registered: {unregistered:?}");
+    }
+}
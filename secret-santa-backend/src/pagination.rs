@@ -0,0 +1,109 @@
+//! Cursors of `/get-groups`.
+//!
+//! A cursor holds the sort key and id of the last group of a page, so the next
+//! page starts right after it even if groups were added or deleted meanwhile.
+//! It is opaque to clients: JSON, hex-encoded.
+
+use crate::errors::ServiceError;
+use crate::json_models::{GroupSort, SortOrder};
+use crate::models::Group;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct GroupCursor {
+    pub sort: GroupSort,
+    pub order: SortOrder,
+    pub id: i32,
+    /// Name of the last group when sorting by name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Sort timestamp of the last group otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub at: Option<DateTime<Utc>>,
+}
+
+impl GroupCursor {
+    pub fn after(group: &Group, sort: GroupSort, order: SortOrder) -> Self {
+        let (name, at) = match sort {
+            GroupSort::Name => (Some(group.gname.clone()), None),
+            GroupSort::CreatedAt => (None, Some(group.created_at)),
+            GroupSort::UpdatedAt => (None, Some(group.updated_at)),
+        };
+        Self { sort, order, id: group.id, name, at }
+    }
+
+    pub fn encode(&self) -> String {
+        hex::encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    /// Reads a cursor, which must come from a listing with the same order.
+    pub fn decode(cursor: &str, sort: GroupSort, order: SortOrder) -> Result<Self, ServiceError> {
+        let invalid = || ServiceError::BadRequest("Invalid cursor".to_string());
+        let bytes = hex::decode(cursor).map_err(|_| invalid())?;
+        let cursor: Self = serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+        let has_key = match sort {
+            GroupSort::Name => cursor.name.is_some(),
+            GroupSort::CreatedAt | GroupSort::UpdatedAt => cursor.at.is_some(),
+        };
+        if cursor.sort != sort || cursor.order != order || !has_key {
+            return Err(ServiceError::BadRequest("Cursor belongs to a different sort order".to_string()));
+        }
+        Ok(cursor)
+    }
+}
+
+/// Pattern for a case-insensitive substring search with `ILIKE`.
+pub fn contains_pattern(search: &str) -> String {
+    let mut pattern = String::with_capacity(search.len() + 2);
+    pattern.push('%');
+    for c in search.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::MemberVisibility;
+
+    fn group() -> Group {
+        let now = Utc::now();
+        Group {
+            id: 7,
+            gname: "friends".to_string(),
+            is_close: false,
+            is_revealed: false,
+            created_at: now,
+            updated_at: now,
+            capacity: None,
+            draw_date: None,
+            budget: None,
+            member_visibility: MemberVisibility::Admins,
+        }
+    }
+
+    #[test]
+    fn cursor_round_trips_and_keeps_its_order() {
+        let group = group();
+        let cursor = GroupCursor::after(&group, GroupSort::CreatedAt, SortOrder::Desc);
+        let encoded = cursor.encode();
+
+        assert_eq!(GroupCursor::decode(&encoded, GroupSort::CreatedAt, SortOrder::Desc).unwrap(), cursor);
+        assert_eq!(cursor.at, Some(group.created_at));
+        assert!(GroupCursor::decode(&encoded, GroupSort::CreatedAt, SortOrder::Asc).is_err());
+        assert!(GroupCursor::decode(&encoded, GroupSort::Name, SortOrder::Desc).is_err());
+        assert!(GroupCursor::decode("not hex", GroupSort::CreatedAt, SortOrder::Desc).is_err());
+    }
+
+    #[test]
+    fn search_pattern_escapes_wildcards() {
+        assert_eq!(contains_pattern("office"), "%office%");
+        assert_eq!(contains_pattern("100%_a\\b"), "%100\\%\\_a\\\\b%");
+    }
+}
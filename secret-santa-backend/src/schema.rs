@@ -1,11 +1,36 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    audit_log (id) {
+        id -> Int4,
+        group_id -> Nullable<Int4>,
+        group_name -> Nullable<Text>,
+        actor -> Nullable<Text>,
+        action -> Varchar,
+        target -> Nullable<Text>,
+        outcome -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    exclusions (id) {
+        id -> Int4,
+        group_id -> Int4,
+        user_id -> Int4,
+        excluded_user_id -> Int4,
+    }
+}
+
 diesel::table! {
     members (id) {
         id -> Int4,
         user_id -> Int4,
         group_id -> Int4,
         urole -> Int4,
+        wishlist -> Nullable<Text>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
     }
 }
 
@@ -15,6 +40,8 @@ diesel::table! {
         group_id -> Int4,
         santa_id -> Int4,
         recipient_id -> Int4,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
     }
 }
 
@@ -23,6 +50,13 @@ diesel::table! {
         id -> Int4,
         gname -> Varchar,
         is_close -> Bool,
+        is_revealed -> Bool,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        capacity -> Nullable<Int4>,
+        draw_date -> Nullable<Date>,
+        budget -> Nullable<Int4>,
+        member_visibility -> Int4,
     }
 }
 
@@ -30,9 +64,47 @@ diesel::table! {
     users (id) {
         id -> Int4,
         name -> Varchar,
+        email -> Nullable<Varchar>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    webhook_deliveries (id) {
+        id -> Int4,
+        webhook_id -> Int4,
+        event -> Varchar,
+        attempt -> Int4,
+        status_code -> Nullable<Int4>,
+        error -> Nullable<Text>,
+        success -> Bool,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    webhooks (id) {
+        id -> Int4,
+        group_id -> Nullable<Int4>,
+        url -> Text,
+        secret -> Nullable<Text>,
     }
 }
 
+diesel::joinable!(exclusions -> sgroups (group_id));
+diesel::joinable!(members -> sgroups (group_id));
 diesel::joinable!(members -> users (user_id));
+diesel::joinable!(webhook_deliveries -> webhooks (webhook_id));
+diesel::joinable!(webhooks -> sgroups (group_id));
 
-diesel::allow_tables_to_appear_in_same_query!(members, santas, sgroups, users,);
+diesel::allow_tables_to_appear_in_same_query!(
+    audit_log,
+    exclusions,
+    members,
+    santas,
+    sgroups,
+    users,
+    webhook_deliveries,
+    webhooks,
+);
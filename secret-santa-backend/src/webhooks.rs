@@ -0,0 +1,565 @@
+//! Outgoing webhooks: group events POSTed as JSON to registered URLs.
+//!
+//! Every request carries the event name in `X-Santa-Event` and the hex encoded
+//! HMAC-SHA256 of the body, keyed with the webhook's secret, in
+//! `X-Santa-Signature` as `sha256=<hex>`. Deliveries run on a few background
+//! threads, one at a time per webhook, so a slow or unreachable endpoint only
+//! delays its own events. Failed ones are retried with exponential backoff
+//! without holding a thread while they wait; every attempt is reported to a
+//! callback that writes the delivery log. The threads start with the first
+//! delivery, and at shutdown get a few seconds to finish what is left.
+//!
+//! A webhook URL must not be a way to reach the service's own network: its host
+//! has to resolve to public addresses only, unless the operator allowed the host
+//! with `webhook_allowed_hosts`. The check runs when a webhook is registered and
+//! again on every delivery, whose connection goes to the checked addresses, so a
+//! DNS record changed after registration doesn't get around it.
+
+use async_std::channel;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashSet;
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use tide::http::url::{Host, Url};
+use tide::log;
+
+pub const EVENT_HEADER: &str = "X-Santa-Event";
+pub const SIGNATURE_HEADER: &str = "X-Santa-Signature";
+
+pub const MAX_URL_LEN: usize = 2000;
+pub const MAX_SECRET_LEN: usize = 200;
+
+const MAX_ATTEMPTS: i32 = 5;
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const DELIVERY_THREADS: usize = 4;
+/// How long shutting down waits for pending deliveries and their retries.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct Delivery {
+    pub webhook_id: i32,
+    pub url: String,
+    pub secret: String,
+    pub event: &'static str,
+    pub body: String,
+}
+
+/// Outcome of one attempt to deliver an event.
+pub struct DeliveryAttempt {
+    pub webhook_id: i32,
+    pub event: &'static str,
+    pub attempt: i32,
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub success: bool,
+}
+
+/// Value of the signature header for `body`.
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Whether `ip` is reachable from the internet. Loopback, private, link-local
+/// (which holds the cloud metadata endpoints), shared, reserved and multicast
+/// addresses are not, and neither are IPv6 addresses that embed one of those.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                || (a == 100 && (64..128).contains(&b))
+                || (a, b, c) == (192, 0, 0)
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(ip.into());
+            }
+            // NAT64 prefix, which translates to the IPv4 address in the last 32 bits.
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                let [.., hi, lo] = segments;
+                return is_public(IpAddr::from((u32::from(hi) << 16 | u32::from(lo)).to_be_bytes()));
+            }
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                || segments[0] & 0xfe00 == 0xfc00
+                || segments[0] & 0xffc0 == 0xfe80)
+        }
+    }
+}
+
+/// Which hosts webhooks may be delivered to: those resolving to public
+/// addresses, and the ones the operator allowed whatever their addresses.
+#[derive(Debug, Clone, Default)]
+pub struct UrlPolicy {
+    allowed_hosts: HashSet<String>,
+}
+
+impl UrlPolicy {
+    pub fn new<S: AsRef<str>>(allowed_hosts: &[S]) -> Self {
+        Self {
+            allowed_hosts: allowed_hosts.iter().map(|host| host.as_ref().to_ascii_lowercase()).collect(),
+        }
+    }
+
+    /// Checks that `url` is a plain http(s) URL whose host may be delivered to,
+    /// resolving it; the error says why not.
+    pub fn check(&self, url: &str) -> Result<(), String> {
+        let invalid = || "Webhook URL must be an http or https URL".to_string();
+        if url.len() > MAX_URL_LEN {
+            return Err(format!("Webhook URL must have at most {MAX_URL_LEN} characters"));
+        }
+        let url = Url::parse(url).map_err(|_| invalid())?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(invalid());
+        }
+        let host = match url.host().ok_or_else(invalid)? {
+            Host::Domain(domain) => domain.to_string(),
+            Host::Ipv4(ip) => ip.to_string(),
+            Host::Ipv6(ip) => ip.to_string(),
+        };
+        let port = url.port_or_known_default().ok_or_else(invalid)?;
+        self.resolve_host(&host, port).map(|_| ()).map_err(|e| format!("Webhook URL is not allowed: {e}"))
+    }
+
+    /// Addresses of `host`, which must all be public unless the host is allowed.
+    fn resolve_host(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        let addresses: Vec<SocketAddr> = (host, port).to_socket_addrs()?.collect();
+        if !self.allowed_hosts.contains(&host.to_ascii_lowercase()) {
+            if let Some(address) = addresses.iter().find(|address| !is_public(address.ip())) {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("{host} resolves to the non-public address {}", address.ip()),
+                ));
+            }
+        }
+        Ok(addresses)
+    }
+}
+
+/// Resolves the hosts of deliveries, so that they only connect to addresses
+/// the policy allows.
+impl ureq::Resolver for UrlPolicy {
+    fn resolve(&self, netloc: &str) -> io::Result<Vec<SocketAddr>> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("invalid address {netloc}"));
+        let (host, port) = netloc.rsplit_once(':').ok_or_else(invalid)?;
+        let port = port.parse().map_err(|_| invalid())?;
+        self.resolve_host(host.trim_start_matches('[').trim_end_matches(']'), port)
+    }
+}
+
+struct Pending {
+    delivery: Delivery,
+    attempt: i32,
+    due: Instant,
+}
+
+enum Message {
+    Deliver(Delivery),
+    /// A worker is done with an attempt; a failed delivery comes back to be retried.
+    Attempted { webhook_id: i32, retry: Option<Pending> },
+    Shutdown,
+}
+
+fn attempt(agent: &ureq::Agent, pending: &Pending) -> DeliveryAttempt {
+    let Delivery { webhook_id, url, secret, event, body } = &pending.delivery;
+    let result = agent.post(url)
+        .set("Content-Type", "application/json")
+        .set(EVENT_HEADER, event)
+        .set(SIGNATURE_HEADER, &sign(secret, body))
+        .send_string(body);
+    // Without following redirects, ureq returns their responses as Ok; only a
+    // 2xx status counts as delivered.
+    let (status_code, error) = match result {
+        Ok(response) if (200..300).contains(&response.status()) => (Some(response.status() as i32), None),
+        Ok(response) => {
+            let code = response.status();
+            (Some(code as i32), Some(format!("HTTP status {code}")))
+        }
+        Err(ureq::Error::Status(code, _)) => (Some(code as i32), Some(format!("HTTP status {code}"))),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    DeliveryAttempt {
+        webhook_id: *webhook_id,
+        event,
+        attempt: pending.attempt,
+        status_code,
+        success: error.is_none(),
+        error,
+    }
+}
+
+/// Hands due deliveries to the workers, at most one at a time per webhook so
+/// that a slow endpoint only holds up its own deliveries, and keeps failed ones
+/// until their retry is due. Stops after a shutdown once nothing is left to
+/// deliver or retry, or when [`SHUTDOWN_TIMEOUT`] has passed, dropping the rest.
+fn schedule(messages: mpsc::Receiver<Message>, jobs: channel::Sender<Pending>) {
+    let mut waiting: Vec<Pending> = Vec::new();
+    let mut busy: HashSet<i32> = HashSet::new();
+    let mut deadline: Option<Instant> = None;
+    while !(deadline.is_some() && busy.is_empty() && waiting.is_empty()) {
+        // Deliveries to a busy webhook wait for its current attempt to end.
+        let next_due = waiting.iter()
+            .filter(|pending| !busy.contains(&pending.delivery.webhook_id))
+            .map(|pending| pending.due)
+            .chain(deadline)
+            .min();
+        let message = match next_due {
+            Some(due) => messages.recv_timeout(due.saturating_duration_since(Instant::now())),
+            None => messages.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match message {
+            Ok(Message::Deliver(delivery)) => waiting.push(Pending { delivery, attempt: 1, due: Instant::now() }),
+            Ok(Message::Attempted { webhook_id, retry }) => {
+                busy.remove(&webhook_id);
+                waiting.extend(retry);
+            }
+            Ok(Message::Shutdown) => deadline = Some(Instant::now() + SHUTDOWN_TIMEOUT),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+            for pending in &waiting {
+                log::warn!(
+                    "Dropping webhook {} delivery of {} (attempt {}) at shutdown",
+                    pending.delivery.webhook_id,
+                    pending.delivery.event,
+                    pending.attempt
+                );
+            }
+            if !busy.is_empty() {
+                log::warn!("Not retrying {} webhook deliveries still in progress at shutdown", busy.len());
+            }
+            return;
+        }
+
+        let now = Instant::now();
+        let mut i = 0;
+        while i < waiting.len() {
+            if waiting[i].due <= now && busy.insert(waiting[i].delivery.webhook_id) {
+                if jobs.try_send(waiting.remove(i)).is_err() {
+                    log::error!("Webhook workers have stopped");
+                    return;
+                }
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Makes the attempts the scheduler hands out and reports back on each.
+fn work(
+    jobs: channel::Receiver<Pending>,
+    messages: mpsc::Sender<Message>,
+    agent: ureq::Agent,
+    log_attempt: Arc<dyn Fn(DeliveryAttempt) + Send + Sync>,
+) {
+    while let Ok(mut pending) = jobs.recv_blocking() {
+        let result = attempt(&agent, &pending);
+        let success = result.success;
+        if !success {
+            log::warn!(
+                "Webhook {} delivery of {} failed (attempt {}): {}",
+                result.webhook_id,
+                result.event,
+                result.attempt,
+                result.error.as_deref().unwrap_or_default()
+            );
+        }
+        if panic::catch_unwind(AssertUnwindSafe(|| log_attempt(result))).is_err() {
+            log::error!("Recording a webhook delivery panicked");
+        }
+        let webhook_id = pending.delivery.webhook_id;
+        let retry = (!success && pending.attempt < MAX_ATTEMPTS).then(|| {
+            pending.due = Instant::now() + FIRST_RETRY_DELAY * 2u32.pow(pending.attempt as u32 - 1);
+            pending.attempt += 1;
+            pending
+        });
+        if messages.send(Message::Attempted { webhook_id, retry }).is_err() {
+            return;
+        }
+    }
+}
+
+type LogAttempt = Arc<dyn Fn(DeliveryAttempt) + Send + Sync>;
+
+struct Worker {
+    sender: mpsc::Sender<Message>,
+    /// Disconnects once every thread has stopped, each holding a sender.
+    stopped: Mutex<mpsc::Receiver<()>>,
+}
+
+impl Worker {
+    fn start(policy: &UrlPolicy, log_attempt: &LogAttempt) -> Self {
+        // Redirects are not followed: the policy applies to the registered URL.
+        let agent = ureq::AgentBuilder::new()
+            .timeout(REQUEST_TIMEOUT)
+            .redirects(0)
+            .resolver(policy.clone())
+            .build();
+        let (sender, messages) = mpsc::channel();
+        let (jobs, queue) = channel::unbounded();
+        let (running, stopped) = mpsc::channel::<()>();
+        for i in 0..DELIVERY_THREADS {
+            let (queue, sender, agent, log_attempt) = (queue.clone(), sender.clone(), agent.clone(), log_attempt.clone());
+            let running = running.clone();
+            thread::Builder::new()
+                .name(format!("webhooks-{i}"))
+                .spawn(move || {
+                    let _running = running;
+                    work(queue, sender, agent, log_attempt)
+                })
+                .expect("cannot spawn webhook thread");
+        }
+        thread::Builder::new()
+            .name("webhooks".to_string())
+            .spawn(move || {
+                let _running = running;
+                schedule(messages, jobs)
+            })
+            .expect("cannot spawn webhook thread");
+        Self { sender, stopped: Mutex::new(stopped) }
+    }
+}
+
+impl Drop for Worker {
+    /// Finishes pending deliveries, including retries, before the process exits,
+    /// waiting at most about [`SHUTDOWN_TIMEOUT`] for them.
+    fn drop(&mut self) {
+        let _ = self.sender.send(Message::Shutdown);
+        // The scheduler closes the job queue when it stops, which stops the workers
+        // once their current attempt is over.
+        let limit = SHUTDOWN_TIMEOUT + Duration::from_secs(1);
+        let stopped = self.stopped.get_mut().unwrap_or_else(|e| e.into_inner());
+        if let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(limit) {
+            log::warn!("Stopped waiting for webhook deliveries still in progress after {limit:?}");
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Webhooks {
+    policy: UrlPolicy,
+    log_attempt: LogAttempt,
+    /// Started by the first delivery, so that commands publishing no events
+    /// don't run it.
+    worker: Arc<OnceLock<Worker>>,
+}
+
+impl Webhooks {
+    pub fn new(policy: UrlPolicy, log_attempt: impl Fn(DeliveryAttempt) + Send + Sync + 'static) -> Self {
+        Self {
+            policy,
+            log_attempt: Arc::new(log_attempt),
+            worker: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// Checks that a webhook may be registered for `url`; see [`UrlPolicy::check`].
+    pub fn check_url(&self, url: &str) -> Result<(), String> {
+        self.policy.check(url)
+    }
+
+    pub fn deliver(&self, delivery: Delivery) {
+        let worker = self.worker.get_or_init(|| Worker::start(&self.policy, &self.log_attempt));
+        if worker.sender.send(Message::Deliver(delivery)).is_err() {
+            log::error!("Webhook worker has stopped");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::JoinHandle;
+
+    #[test]
+    fn signs_with_hmac_sha256() {
+        // RFC 4231, test case 2.
+        assert_eq!(
+            sign("Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    /// Answers requests with `statuses` in turn, each after `delay`, returning
+    /// the raw requests it received.
+    fn server(statuses: &'static [&'static str], delay: Duration) -> (String, JoinHandle<Vec<String>>) {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for status in statuses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buffer = [0u8; 4096];
+                let read = stream.read(&mut buffer).unwrap();
+                requests.push(String::from_utf8_lossy(&buffer[..read]).to_string());
+                thread::sleep(delay);
+                write!(stream, "HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").unwrap();
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    fn delivery(webhook_id: i32, url: &str) -> Delivery {
+        Delivery {
+            webhook_id,
+            url: url.to_string(),
+            secret: "secret".to_string(),
+            event: "member_joined",
+            body: "{}".to_string(),
+        }
+    }
+
+    #[test]
+    fn retries_failed_deliveries() {
+        let (url, server) = server(&["500 Internal Server Error", "200 OK"], Duration::ZERO);
+        let attempts = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = attempts.clone();
+        let policy = UrlPolicy::new(&["127.0.0.1"]);
+        let webhooks = Webhooks::new(policy, move |attempt| {
+            log.lock().unwrap().push((attempt.attempt, attempt.status_code, attempt.success));
+        });
+
+        webhooks.deliver(delivery(1, &url));
+        drop(webhooks);
+
+        assert_eq!(*attempts.lock().unwrap(), vec![(1, Some(500), false), (2, Some(200), true)]);
+        let requests = server.join().unwrap();
+        let signature = format!("{SIGNATURE_HEADER}: {}", sign("secret", "{}"));
+        assert!(requests.iter().all(|request| request.contains(&signature)));
+    }
+
+    #[test]
+    fn shutdown_drops_late_retries() {
+        // Retries are due 1, 2, 4 and 8 seconds after the attempt before, so the
+        // last ones come after the shutdown timeout.
+        let (url, _server) = server(&["500 Internal Server Error"; 5], Duration::ZERO);
+        let attempts = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = attempts.clone();
+        let webhooks = Webhooks::new(UrlPolicy::new(&["127.0.0.1"]), move |attempt| {
+            log.lock().unwrap().push(attempt.attempt);
+        });
+
+        webhooks.deliver(delivery(1, &url));
+        let start = Instant::now();
+        drop(webhooks);
+
+        assert!(start.elapsed() < SHUTDOWN_TIMEOUT + Duration::from_secs(1));
+        assert_eq!(*attempts.lock().unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn redirects_are_failures() {
+        let (url, server) = server(&["302 Found", "200 OK"], Duration::ZERO);
+        let attempts = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = attempts.clone();
+        let webhooks = Webhooks::new(UrlPolicy::new(&["127.0.0.1"]), move |attempt| {
+            log.lock().unwrap().push((attempt.status_code, attempt.error));
+        });
+
+        webhooks.deliver(delivery(1, &url));
+        drop(webhooks);
+
+        assert_eq!(
+            *attempts.lock().unwrap(),
+            vec![(Some(302), Some("HTTP status 302".to_string())), (Some(200), None)]
+        );
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn slow_endpoint_does_not_hold_up_others() {
+        let (slow_url, slow) = server(&["200 OK", "200 OK"], Duration::from_millis(500));
+        let (fast_url, fast) = server(&["200 OK"; 4], Duration::ZERO);
+        let attempts = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = attempts.clone();
+        let policy = UrlPolicy::new(&["127.0.0.1"]);
+        let webhooks = Webhooks::new(policy, move |attempt| log.lock().unwrap().push(attempt.webhook_id));
+
+        // More deliveries to the slow endpoint than there are threads.
+        for _ in 0..2 {
+            webhooks.deliver(delivery(1, &slow_url));
+        }
+        for _ in 0..4 {
+            webhooks.deliver(delivery(2, &fast_url));
+        }
+        drop(webhooks);
+
+        assert_eq!(*attempts.lock().unwrap(), vec![2, 2, 2, 2, 1, 1]);
+        slow.join().unwrap();
+        fast.join().unwrap();
+    }
+
+    #[test]
+    fn accepts_only_http_urls() {
+        let policy = UrlPolicy::default();
+        assert!(policy.check("https://93.184.215.14/hooks/santa").is_ok());
+        assert!(policy.check("http://[2606:2800:21f:cb07:6820:80da:af6b:8b2c]:8080/").is_ok());
+        assert!(policy.check("ftp://93.184.215.14/").is_err());
+        assert!(policy.check("not a url").is_err());
+    }
+
+    #[test]
+    fn rejects_non_public_addresses() {
+        let policy = UrlPolicy::default();
+        for url in [
+            "http://localhost:9000/",
+            "http://127.0.0.1/",
+            "http://10.1.2.3/",
+            "http://172.16.0.1/",
+            "http://192.168.1.1/",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://100.100.100.200/",
+            "http://0.0.0.0/",
+            "http://[::1]/",
+            "http://[fd00:ec2::254]/",
+            "http://[fe80::1]/",
+            "http://[::ffff:127.0.0.1]/",
+            "http://[64:ff9b::a9fe:a9fe]/",
+        ] {
+            assert!(policy.check(url).is_err(), "{url} was accepted");
+        }
+    }
+
+    #[test]
+    fn allows_hosts_listed_by_the_operator() {
+        let policy = UrlPolicy::new(&["LocalHost", "10.1.2.3"]);
+        assert!(policy.check("http://localhost:9000/").is_ok());
+        assert!(policy.check("http://10.1.2.3/").is_ok());
+        assert!(policy.check("http://10.1.2.4/").is_err());
+    }
+
+    #[test]
+    fn deliveries_connect_only_to_allowed_addresses() {
+        use ureq::Resolver;
+
+        let policy = UrlPolicy::default();
+        assert!(policy.resolve("127.0.0.1:80").is_err());
+        assert!(policy.resolve("[::1]:443").is_err());
+        assert_eq!(policy.resolve("93.184.215.14:443").unwrap(), vec!["93.184.215.14:443".parse().unwrap()]);
+    }
+}
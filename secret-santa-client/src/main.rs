@@ -1,5 +1,9 @@
 use std::env;
-use ureq::{Error};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use ureq::{Agent, AgentBuilder, Error};
 use clap::Parser;
 
 #[derive(Parser, Debug)]
@@ -14,61 +18,223 @@ struct Args
     group_name: Option<String>,
     #[arg(short, long)]
     new_admin: Option<String>,
+    /// Address for notification emails, for registr-user and set-email
+    #[arg(short, long)]
+    email: Option<String>,
+    /// CSV file for import-members
+    #[arg(short, long)]
+    file: Option<PathBuf>,
+    /// json or csv, for export-assignments
+    #[arg(long)]
+    format: Option<String>,
+    /// name, created_at or updated_at, for get-groups
+    #[arg(long)]
+    sort: Option<String>,
+    /// asc or desc, for get-groups
+    #[arg(long)]
+    order: Option<String>,
+    /// Part of the group name, for get-groups
+    #[arg(long)]
+    search: Option<String>,
+    /// next_cursor of the previous page, for get-groups
+    #[arg(long)]
+    cursor: Option<String>,
+    /// Most members of the group, for create-group
+    #[arg(long)]
+    capacity: Option<i32>,
+    /// Day of the draw as YYYY-MM-DD, for create-group
+    #[arg(long)]
+    draw_date: Option<String>,
+    /// Gift budget, for create-group
+    #[arg(long)]
+    budget: Option<i32>,
+    /// Only groups with (true) or without (false) a free spot, for get-groups
+    #[arg(long)]
+    has_space: Option<bool>,
+    /// Earliest draw date as YYYY-MM-DD, for get-groups
+    #[arg(long)]
+    draw_from: Option<String>,
+    /// Latest draw date as YYYY-MM-DD, for get-groups
+    #[arg(long)]
+    draw_until: Option<String>,
+    /// Lowest budget, for get-groups
+    #[arg(long)]
+    min_budget: Option<i32>,
+    /// Highest budget, for get-groups
+    #[arg(long)]
+    max_budget: Option<i32>,
+    /// admins, members or public, for set-member-visibility
+    #[arg(long)]
+    visibility: Option<String>,
+    /// Target of add-webhook
+    #[arg(long)]
+    url: Option<String>,
+    /// Signing key for add-webhook
+    #[arg(long, env = "SANTA_WEBHOOK_SECRET")]
+    secret: Option<String>,
+    /// Webhook id for remove-webhook
+    #[arg(long)]
+    webhook_id: Option<i32>,
+    /// Cursor for webhook-deliveries and audit-log, the next_before of the previous page
+    #[arg(long)]
+    before: Option<i32>,
+    /// PEM file with CA certificates to trust instead of the built-in roots
+    #[arg(long, env = "CA_BUNDLE")]
+    ca_bundle: Option<PathBuf>,
+}
+
+fn make_agent(ca_bundle: Option<&Path>) -> Agent {
+    let mut builder = AgentBuilder::new();
+    if let Some(path) = ca_bundle {
+        let file = File::open(path).expect("CA bundle must be readable");
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut BufReader::new(file)) {
+            roots.add(cert.expect("CA bundle must contain PEM certificates"))
+                .expect("CA bundle must contain valid certificates");
+        }
+        let tls_config = rustls::ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        builder = builder.tls_config(Arc::new(tls_config));
+    }
+    builder.build()
 }
 
 fn main() {
     let args = Args::parse();
     let addr = env::var("URL").expect("env var url must exist");
+    let agent = make_agent(args.ca_bundle.as_deref());
     let resp = match args.command.as_str() {
-        "registr-user" => ureq::post(format!("{}/{}", addr, args.command).as_str())
+        "registr-user" => agent.post(format!("{}/{}", addr, args.command).as_str())
             .send_json(ureq::json!({
-                "username" : args.username
+                "username" : args.username,
+                "email" : args.email
             })),
-        "create-group" => ureq::post(format!("{}/{}", addr, args.command).as_str())
+        "set-email" => agent.post(format!("{}/{}", addr, args.command).as_str())
             .send_json(ureq::json!({
                 "username" : args.username,
-                "group_name" : args.group_name
+                "email" : args.email
             })),
-        "group-members" => ureq::get(format!("{}/{}", addr, args.command).as_str())
+        "create-group" => agent.post(format!("{}/{}", addr, args.command).as_str())
             .send_json(ureq::json!({
                 "username" : args.username,
-                "group_name" : args.group_name
+                "group_name" : args.group_name,
+                "capacity" : args.capacity,
+                "draw_date" : args.draw_date,
+                "budget" : args.budget
             })),
-        "join-group" => ureq::post(format!("{}/{}", addr, args.command).as_str())
+        "my-groups" => agent.get(format!("{}/{}", addr, args.command).as_str())
+            .query("username", args.username.as_deref().unwrap_or_default())
+            .call(),
+        "group-members" => agent.get(format!("{}/{}", addr, args.command).as_str())
+            .query("username", args.username.as_deref().unwrap_or_default())
+            .query("group_name", args.group_name.as_deref().unwrap_or_default())
+            .call(),
+        "join-group" => agent.post(format!("{}/{}", addr, args.command).as_str())
             .send_json(ureq::json!({
                 "username" : args.username,
                 "group_name" : args.group_name
             })),
-        "delete-group" => ureq::post(format!("{}/{}", addr, args.command).as_str())
+        "delete-group" => agent.post(format!("{}/{}", addr, args.command).as_str())
             .send_json(ureq::json!({
                 "username" : args.username,
                 "group_name": args.group_name
             })),
-        "get-recipient-name" => ureq::get(format!("{}/{}", addr, args.command).as_str())
+        "set-member-visibility" => agent.post(format!("{}/{}", addr, args.command).as_str())
             .send_json(ureq::json!({
                 "username": args.username,
-                "group_name": args.group_name
+                "group_name": args.group_name,
+                "visibility": args.visibility
             })),
-        "add-admin" => ureq::post(format!("{}/{}", addr, args.command).as_str())
+        "import-members" => agent.post(format!("{}/{}", addr, args.command).as_str())
+            .query("username", args.username.as_deref().unwrap_or_default())
+            .query("group_name", args.group_name.as_deref().unwrap_or_default())
+            .set("Content-Type", "text/csv")
+            .send_string(&std::fs::read_to_string(args.file.expect("--file is required"))
+                .expect("CSV file must be readable")),
+        "get-recipient-name" => agent.get(format!("{}/{}", addr, args.command).as_str())
+            .query("username", args.username.as_deref().unwrap_or_default())
+            .query("group_name", args.group_name.as_deref().unwrap_or_default())
+            .call(),
+        "add-admin" => agent.post(format!("{}/{}", addr, args.command).as_str())
             .send_json(ureq::json!({
                 "username" : args.username,
                 "group_name": args.group_name,
                 "new_admin": args.new_admin
             })),
-        "start-secret-santa" => ureq::post(format!("{}/{}", addr, args.command).as_str())
+        "start-secret-santa" => agent.post(format!("{}/{}", addr, args.command).as_str())
+            .send_json(ureq::json!({
+                "username": args.username,
+                "group_name": args.group_name
+            })),
+        "revoke-admin-rights" => agent.post(format!("{}/{}", addr, args.command).as_str())
             .send_json(ureq::json!({
                 "username": args.username,
                 "group_name": args.group_name
             })),
-        "revoke-admin-rights" => ureq::post(format!("{}/{}", addr, args.command).as_str())
+        "reveal-assignments" => agent.post(format!("{}/{}", addr, args.command).as_str())
             .send_json(ureq::json!({
                 "username": args.username,
                 "group_name": args.group_name
             })),
-        "get-groups" => ureq::get(format!("{}/{}", addr, args.command).as_str())
+        "export-assignments" => agent.get(format!("{}/{}", addr, args.command).as_str())
+            .query("username", args.username.as_deref().unwrap_or_default())
+            .query("group_name", args.group_name.as_deref().unwrap_or_default())
+            .query("format", args.format.as_deref().unwrap_or("json"))
+            .call(),
+        "add-webhook" => agent.post(format!("{}/{}", addr, args.command).as_str())
+            .send_json(ureq::json!({
+                "username": args.username,
+                "group_name": args.group_name,
+                "url": args.url,
+                "secret": args.secret
+            })),
+        "remove-webhook" => agent.post(format!("{}/{}", addr, args.command).as_str())
             .send_json(ureq::json!({
-                "username": args.username
+                "username": args.username,
+                "group_name": args.group_name,
+                "webhook_id": args.webhook_id
             })),
+        "webhooks" => agent.get(format!("{}/{}", addr, args.command).as_str())
+            .query("username", args.username.as_deref().unwrap_or_default())
+            .query("group_name", args.group_name.as_deref().unwrap_or_default())
+            .call(),
+        "webhook-deliveries" | "audit-log" => {
+            let request = agent.get(format!("{}/{}", addr, args.command).as_str())
+                .query("username", args.username.as_deref().unwrap_or_default())
+                .query("group_name", args.group_name.as_deref().unwrap_or_default());
+            match args.before {
+                Some(before) => request.query("before", &before.to_string()).call(),
+                None => request.call(),
+            }
+        }
+        "get-groups" => {
+            let mut request = agent.get(format!("{}/{}", addr, args.command).as_str())
+                .query("sort", args.sort.as_deref().unwrap_or("name"))
+                .query("order", args.order.as_deref().unwrap_or("asc"));
+            if let Some(search) = &args.search {
+                request = request.query("search", search);
+            }
+            if let Some(cursor) = &args.cursor {
+                request = request.query("cursor", cursor);
+            }
+            if let Some(has_space) = args.has_space {
+                request = request.query("has_space", &has_space.to_string());
+            }
+            if let Some(draw_from) = &args.draw_from {
+                request = request.query("draw_from", draw_from);
+            }
+            if let Some(draw_until) = &args.draw_until {
+                request = request.query("draw_until", draw_until);
+            }
+            if let Some(min_budget) = args.min_budget {
+                request = request.query("min_budget", &min_budget.to_string());
+            }
+            if let Some(max_budget) = args.max_budget {
+                request = request.query("max_budget", &max_budget.to_string());
+            }
+            request.call()
+        }
         _ => panic!("unexpected request")
     };
     match resp {
@@ -77,7 +243,14 @@ fn main() {
         }
         Err(Error::Status(code, response)) => {
             println!("Status code: {code} {0:?}", response.status_text());
-            println!("Response: {response:?}");
+            match response.into_json::<serde_json::Value>() {
+                Ok(body) => println!(
+                    "Error {}: {}",
+                    body["code"].as_str().unwrap_or("UNKNOWN"),
+                    body["error_message"].as_str().unwrap_or_default()
+                ),
+                Err(_) => println!("Response body is not a valid error message"),
+            }
         }
         Err(_) => {}
     }